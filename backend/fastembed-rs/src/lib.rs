@@ -1,5 +1,388 @@
-/// สร้าง embedding vector จากข้อความ (mock)
-/// ตัวอย่างนี้ แปลงแต่ละ byte ของข้อความเป็น float 0..1
-pub fn embed(text: &str) -> Vec<f32> {
-    text.bytes().map(|b| b as f32 / 255.0).collect()
-}
\ No newline at end of file
+//! สร้าง sentence embedding จาก ONNX model ที่อยู่ในเครื่อง (ไม่ดาวน์โหลดตอน runtime)
+//!
+//! โฟลเดอร์ model ต้องมี `model.onnx` กับ `tokenizer.json` (เช่น export ของ
+//! sentence-transformers) embedding ได้จาก mean pooling ของ token ตาม attention mask
+//! แล้ว normalize ให้ยาว 1 เสมอ
+//!
+//! ONNX Runtime ถูกโหลดแบบ dynamic: ตั้ง `ORT_DYLIB_PATH` ให้ชี้ไปที่
+//! `libonnxruntime.so` ถ้าไม่ได้อยู่ใน library path ของระบบ
+
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Mutex;
+
+use ort::session::Session;
+use ort::value::Tensor;
+use tokenizers::{Encoding, Tokenizer, TruncationParams};
+
+/// ขนาดของ vector จาก hashing embedder ถ้าไม่ได้ระบุ
+pub const DEFAULT_HASHING_DIM: usize = 384;
+
+/// จำนวน token สูงสุดที่ส่งเข้า model
+const MAX_TOKENS: usize = 256;
+
+/// จำนวนข้อความที่ส่งเข้า model ต่อหนึ่งรอบใน `embed_batch`
+const BATCH_SIZE: usize = 32;
+
+#[derive(Debug)]
+pub enum EmbedError {
+    Io(std::io::Error),
+    Model(String),
+    Tokenizer(String),
+}
+
+impl fmt::Display for EmbedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbedError::Io(e) => write!(f, "io error: {}", e),
+            EmbedError::Model(e) => write!(f, "model error: {}", e),
+            EmbedError::Tokenizer(e) => write!(f, "tokenizer error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EmbedError {}
+
+impl From<std::io::Error> for EmbedError {
+    fn from(e: std::io::Error) -> Self {
+        EmbedError::Io(e)
+    }
+}
+
+impl From<ort::Error> for EmbedError {
+    fn from(e: ort::Error) -> Self {
+        EmbedError::Model(e.to_string())
+    }
+}
+
+enum Backend {
+    Onnx {
+        session: Mutex<Session>,
+        tokenizer: Box<Tokenizer>,
+        /// model บางตัวรับ `token_type_ids` ด้วย บางตัวไม่รับ
+        wants_token_types: bool,
+    },
+    Hashing,
+}
+
+/// ตัวระบุว่า vector มาจาก embedder ไหน ใช้กันไม่ให้ vector จาก model อื่นที่ขนาดเท่ากันปนกัน
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModelId {
+    /// `HASHING` หรือ `ONNX`
+    pub kind: u8,
+    /// FNV-1a (64 bit) ของเนื้อหา `tokenizer.json` ต่อด้วย `model.onnx` (0 สำหรับ hashing)
+    pub fingerprint: u64,
+}
+
+impl ModelId {
+    pub const HASHING: u8 = 1;
+    pub const ONNX: u8 = 2;
+}
+
+impl fmt::Display for ModelId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            Self::HASHING => write!(f, "hashing embedder"),
+            Self::ONNX => write!(f, "ONNX model {:016x}", self.fingerprint),
+            kind => write!(f, "unknown embedder {} ({:016x})", kind, self.fingerprint),
+        }
+    }
+}
+
+/// ตัวสร้าง embedding ขนาดคงที่ `dim()`
+pub struct TextEmbedding {
+    backend: Backend,
+    dim: usize,
+    model_id: ModelId,
+}
+
+impl TextEmbedding {
+    /// โหลด model จากโฟลเดอร์ที่มี `model.onnx` และ `tokenizer.json`
+    /// ขนาดของ vector อ่านจาก output ของ model ตอนโหลด
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, EmbedError> {
+        let dir = dir.as_ref();
+        let model_path = dir.join("model.onnx");
+        let tokenizer_path = dir.join("tokenizer.json");
+
+        for path in [&model_path, &tokenizer_path] {
+            if !path.is_file() {
+                return Err(EmbedError::Io(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("{} not found", path.display()),
+                )));
+            }
+        }
+
+        let model_id = ModelId {
+            kind: ModelId::ONNX,
+            fingerprint: fingerprint(&[&tokenizer_path, &model_path])?,
+        };
+
+        let mut tokenizer = Tokenizer::from_file(&tokenizer_path).map_err(|e| EmbedError::Tokenizer(e.to_string()))?;
+        tokenizer
+            .with_truncation(Some(TruncationParams { max_length: MAX_TOKENS, ..Default::default() }))
+            .map_err(|e| EmbedError::Tokenizer(e.to_string()))?;
+        tokenizer.with_padding(None);
+
+        let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let session = Session::builder()?
+            .with_intra_threads(threads)?
+            .commit_from_file(&model_path)?;
+        let wants_token_types = session.inputs.iter().any(|i| i.name == "token_type_ids");
+
+        let mut embedding = Self {
+            backend: Backend::Onnx {
+                session: Mutex::new(session),
+                tokenizer: Box::new(tokenizer),
+                wants_token_types,
+            },
+            dim: 0,
+            model_id,
+        };
+        embedding.dim = embedding.embed("dimension probe")?.len();
+        Ok(embedding)
+    }
+
+    /// embedder สำรองที่ไม่ต้องใช้ model: hash คำและ character trigram ลง `dim` ช่อง
+    /// จับได้แค่ความเหมือนเชิงคำ ใช้สำหรับ dev/test เมื่อไม่มีไฟล์ model
+    pub fn hashing(dim: usize) -> Self {
+        Self {
+            backend: Backend::Hashing,
+            dim,
+            model_id: ModelId { kind: ModelId::HASHING, fingerprint: 0 },
+        }
+    }
+
+    /// ขนาดของ vector ที่ `embed` คืน
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn model_id(&self) -> ModelId {
+        self.model_id
+    }
+
+    /// true ถ้าใช้ ONNX model จริง
+    pub fn is_model(&self) -> bool {
+        matches!(self.backend, Backend::Onnx { .. })
+    }
+
+    /// สร้าง embedding ที่ normalize แล้ว (ยาว 1) ของข้อความ
+    pub fn embed(&self, text: &str) -> Result<Vec<f32>, EmbedError> {
+        Ok(self.embed_batch(&[text])?.pop().unwrap_or_default())
+    }
+
+    /// สร้าง embedding ของหลายข้อความ คืนตามลำดับที่ส่งมา
+    ///
+    /// ONNX: tokenize พร้อมกันหลาย thread แล้วเรียงตามความยาว ส่งเข้า model ทีละ `BATCH_SIZE` ข้อความ
+    /// โดย pad ให้ยาวเท่าข้อความที่ยาวที่สุดในชุด (ข้อความยาวใกล้กันอยู่ชุดเดียวกันจึง pad น้อย)
+    /// model ใช้ทุก CPU thread ต่อหนึ่งรอบ hashing: แบ่งข้อความให้แต่ละ thread
+    pub fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        let mut vectors = match &self.backend {
+            Backend::Onnx { session, tokenizer, wants_token_types } => {
+                let encodings = tokenizer
+                    .encode_batch(texts.to_vec(), true)
+                    .map_err(|e| EmbedError::Tokenizer(e.to_string()))?;
+                let mut order: Vec<usize> = (0..texts.len()).collect();
+                order.sort_by_key(|&i| encodings[i].len());
+
+                let mut vectors = vec![Vec::new(); texts.len()];
+                let mut session = session.lock().unwrap();
+                for chunk in order.chunks(BATCH_SIZE) {
+                    let batch: Vec<&Encoding> = chunk.iter().map(|&i| &encodings[i]).collect();
+                    for (&i, v) in chunk.iter().zip(embed_onnx(&mut session, *wants_token_types, &batch)?) {
+                        vectors[i] = v;
+                    }
+                }
+                vectors
+            }
+            Backend::Hashing => {
+                let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+                let per_thread = texts.len().div_ceil(threads).max(1);
+                std::thread::scope(|scope| {
+                    let handles: Vec<_> = texts
+                        .chunks(per_thread)
+                        .map(|chunk| scope.spawn(move || chunk.iter().map(|t| embed_hashing(t, self.dim)).collect::<Vec<_>>()))
+                        .collect();
+                    handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+                })
+            }
+        };
+        vectors.iter_mut().for_each(|v| normalize(v));
+        Ok(vectors)
+    }
+}
+
+/// ส่ง encoding หนึ่งชุดเข้า model โดย pad ด้วย 0 (attention mask 0) ให้ยาวเท่ากัน
+fn embed_onnx(session: &mut Session, wants_token_types: bool, batch: &[&Encoding]) -> Result<Vec<Vec<f32>>, EmbedError> {
+    let rows = batch.len();
+    let len = batch.iter().map(|e| e.len()).max().unwrap_or(0);
+    let padded = |values: fn(&Encoding) -> &[u32]| {
+        let mut out = vec![0i64; rows * len];
+        for (row, encoding) in batch.iter().enumerate() {
+            for (o, &x) in out[row * len..].iter_mut().zip(values(encoding)) {
+                *o = x as i64;
+            }
+        }
+        out
+    };
+
+    let ids = Tensor::from_array(([rows, len], padded(Encoding::get_ids)))?;
+    let mask_values = padded(Encoding::get_attention_mask);
+    let mask = Tensor::from_array(([rows, len], mask_values.clone()))?;
+
+    let outputs = if wants_token_types {
+        let types = Tensor::from_array(([rows, len], padded(Encoding::get_type_ids)))?;
+        session.run(ort::inputs![
+            "input_ids" => ids,
+            "attention_mask" => mask,
+            "token_type_ids" => types,
+        ])?
+    } else {
+        session.run(ort::inputs![
+            "input_ids" => ids,
+            "attention_mask" => mask,
+        ])?
+    };
+
+    let (shape, data) = outputs[0].try_extract_tensor::<f32>()?;
+    match shape.len() {
+        // model ที่ pool มาแล้ว: [batch, hidden]
+        2 => {
+            let hidden = shape[1] as usize;
+            Ok(data.chunks(hidden).map(<[f32]>::to_vec).collect())
+        }
+        // last_hidden_state: [batch, seq, hidden] → mean pooling ตาม attention mask
+        3 => {
+            let hidden = shape[2] as usize;
+            let pooled = (0..rows)
+                .map(|row| {
+                    let mut pooled = vec![0.0f32; hidden];
+                    let mut count = 0.0f32;
+                    for t in 0..len {
+                        if mask_values[row * len + t] == 0 {
+                            continue;
+                        }
+                        count += 1.0;
+                        let start = (row * len + t) * hidden;
+                        for (p, x) in pooled.iter_mut().zip(&data[start..start + hidden]) {
+                            *p += x;
+                        }
+                    }
+                    if count > 0.0 {
+                        pooled.iter_mut().for_each(|p| *p /= count);
+                    }
+                    pooled
+                })
+                .collect();
+            Ok(pooled)
+        }
+        n => Err(EmbedError::Model(format!("unexpected output rank {}", n))),
+    }
+}
+
+fn embed_hashing(text: &str, dim: usize) -> Vec<f32> {
+    let mut v = vec![0.0f32; dim];
+    if dim == 0 {
+        return v;
+    }
+    let lower = text.to_lowercase();
+    let mut add = |feature: &str, weight: f32| {
+        let h = fnv1a(feature.as_bytes());
+        let sign = if h >> 63 == 0 { 1.0 } else { -1.0 };
+        v[(h % dim as u64) as usize] += sign * weight;
+    };
+
+    for word in lower.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        add(word, 1.0);
+        let chars: Vec<char> = word.chars().collect();
+        for gram in chars.windows(3) {
+            add(&gram.iter().collect::<String>(), 0.5);
+        }
+    }
+    v
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    fnv1a_update(FNV_OFFSET, bytes)
+}
+
+fn fnv1a_update(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// FNV-1a ของเนื้อหาไฟล์ต่อกันตามลำดับ อ่านทีละส่วน (model.onnx อาจใหญ่หลายร้อย MB)
+fn fingerprint(paths: &[&Path]) -> std::io::Result<u64> {
+    let mut hash = FNV_OFFSET;
+    let mut buf = vec![0u8; 1 << 16];
+    for path in paths {
+        let mut file = std::fs::File::open(path)?;
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hash = fnv1a_update(hash, &buf[..n]);
+        }
+    }
+    Ok(hash)
+}
+
+fn normalize(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("fastembed-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn fingerprint_hashes_the_whole_model_content() {
+        let dir = temp_dir("fingerprint");
+        let tokenizer = dir.join("tokenizer.json");
+        let model = dir.join("model.onnx");
+        std::fs::write(&tokenizer, b"{}").unwrap();
+        // ยาวกว่า buffer ที่ใช้อ่าน เพื่อให้ต่างกันที่ส่วนหลัง ๆ ของไฟล์
+        let mut weights = vec![7u8; 200_000];
+        std::fs::write(&model, &weights).unwrap();
+        let before = fingerprint(&[&tokenizer, &model]).unwrap();
+        assert_eq!(before, fnv1a(&[b"{}".as_slice(), &weights].concat()));
+        assert_eq!(fingerprint(&[&tokenizer, &model]).unwrap(), before);
+
+        // model ขนาดเท่าเดิมแต่ weight ต่างกันต้องได้ fingerprint ต่างกัน
+        weights[150_000] = 8;
+        std::fs::write(&model, &weights).unwrap();
+        assert_ne!(fingerprint(&[&tokenizer, &model]).unwrap(), before);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn model_ids_differ_by_kind_and_full_fingerprint() {
+        let onnx = |fingerprint| ModelId { kind: ModelId::ONNX, fingerprint };
+        assert_eq!(TextEmbedding::hashing(8).model_id(), ModelId { kind: ModelId::HASHING, fingerprint: 0 });
+        assert_ne!(TextEmbedding::hashing(8).model_id(), onnx(0));
+        // ต่างกันเฉพาะ 32 bit บนก็ถือว่าเป็นคนละ model
+        assert_ne!(onnx(1), onnx(1 | 1 << 40));
+        assert_eq!(onnx(1 << 40).to_string(), "ONNX model 0000010000000000");
+    }
+
+    #[test]
+    fn from_dir_requires_model_files() {
+        let dir = temp_dir("missing");
+        assert!(matches!(TextEmbedding::from_dir(&dir), Err(EmbedError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,8 +1,773 @@
-/// Mock function สำหรับค้นหา vector ที่ใกล้เคียง
-/// รับ vector query (slice ของ f32) → คืน Vec<index> ของ vector ที่ match
-/// ในตัวอย่างนี้ return แค่ 5 อันดับแรก (mock)
-pub fn search(query_vector: &[f32]) -> Vec<usize> {
-    // ในโปรเจกต์จริง ต้อง implement เชื่อมกับ spfresh index file
-    // ที่นี่คืน index mock (0..4)
-    (0..5).collect()
+//! Partitioned vector index แบบ SPANN
+//!
+//! vector ทั้งหมดถูกแบ่งเป็น posting list ตาม centroid ที่ใกล้ที่สุด (k-means)
+//! ตอนค้นหาจะเทียบ query กับ centroid ก่อน แล้ว probe เฉพาะ posting ที่ใกล้ที่สุด
+//! `nprobe` อัน จากนั้นคำนวณระยะจริงกับ vector ใน posting เหล่านั้นเพื่อหา top-k
+//!
+//! การ insert ใหม่ใช้แนวทาง LIRE ของ SPFresh: foreground แค่ append vector เข้า posting
+//! ที่ใกล้ที่สุด ส่วนงาน split posting ที่โตเกิน, merge posting ที่เล็กเกิน และ reassign
+//! vector รอบ ๆ จุดที่ถูก split จะถูกเข้าคิวไว้ให้ [`spawn_rebuilder`] ทำใน background
+
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::cmp::Ordering;
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// id ของ vector ใน index (review id ที่ backend แจก)
+pub type VectorId = u64;
+
+type PostingId = usize;
+
+/// ค่าตั้งต้นของการสร้าง index
+#[derive(Clone, Debug)]
+pub struct IndexConfig {
+    /// ขนาดเฉลี่ยของ posting list ที่ต้องการ ใช้คำนวณจำนวน centroid
+    pub target_posting_size: usize,
+    /// จำนวนรอบสูงสุดของ k-means
+    pub kmeans_iterations: usize,
+    /// จำนวน posting สูงสุดที่ vector หนึ่งตัวจะถูก replicate ไปอยู่ (boundary vector)
+    pub replica_count: usize,
+    /// vector จะถูก replicate ไป posting อื่นถ้าระยะถึง centroid นั้น
+    /// ไม่เกิน `(1 + replica_epsilon)` เท่าของระยะถึง centroid ที่ใกล้ที่สุด
+    pub replica_epsilon: f32,
+    /// จำนวน posting ที่ probe ตอนค้นหาถ้าไม่ระบุ
+    pub default_nprobe: usize,
+    /// posting ที่ยาวเกินค่านี้จะถูก split
+    pub max_posting_size: usize,
+    /// posting ที่สั้นกว่าค่านี้จะถูก merge เข้ากับ posting ข้างเคียง (ถ้า posting ข้างเคียงไม่ยาวเกิน `max_posting_size`)
+    pub min_posting_size: usize,
+    /// จำนวน posting ข้างเคียงที่ตรวจ reassign หลัง split
+    pub reassign_neighbors: usize,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            target_posting_size: 64,
+            kmeans_iterations: 16,
+            replica_count: 2,
+            replica_epsilon: 0.1,
+            default_nprobe: 8,
+            max_posting_size: 128,
+            min_posting_size: 8,
+            reassign_neighbors: 8,
+        }
+    }
+}
+
+/// posting list หนึ่งอัน: centroid กับ id ของ vector ที่อยู่ใน partition นี้
+#[derive(Clone, Debug)]
+struct Posting {
+    centroid: Vec<f32>,
+    ids: Vec<VectorId>,
+}
+
+/// พารามิเตอร์ของการค้นหา
+#[derive(Clone, Debug)]
+pub struct SearchParams {
+    /// จำนวนผลลัพธ์สูงสุด
+    pub top_k: usize,
+    /// จำนวน posting ที่ probe (มากขึ้น = recall ดีขึ้นแต่ช้าลง) ถ้า `None` ใช้ `default_nprobe`
+    pub nprobe: Option<usize>,
+    /// ตัดผลลัพธ์ที่ระยะ (L2 ยกกำลังสอง) เกินค่านี้ทิ้ง
+    pub max_distance: Option<f32>,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self {
+            top_k: 10,
+            nprobe: None,
+            max_distance: None,
+        }
+    }
+}
+
+/// งานปรับโครงสร้าง posting ที่รอทำใน background
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Job {
+    Split(PostingId),
+    Merge(PostingId),
+}
+
+/// สถิติของงาน LIRE ที่ทำไปแล้ว
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UpdateStats {
+    pub splits: usize,
+    pub merges: usize,
+    pub reassigned: usize,
+}
+
+/// Index ในหน่วยความจำ เก็บ vector ทั้งหมดและ posting list
+#[derive(Debug)]
+pub struct Index {
+    dim: usize,
+    config: IndexConfig,
+    vectors: HashMap<VectorId, Vec<f32>>,
+    postings: HashMap<PostingId, Posting>,
+    /// posting ที่แต่ละ vector อยู่ (มีได้หลายอันเพราะ replica)
+    placement: HashMap<VectorId, Vec<PostingId>>,
+    next_posting: PostingId,
+    jobs: VecDeque<Job>,
+    stats: UpdateStats,
+}
+
+impl Index {
+    /// สร้าง index ว่างสำหรับ vector ขนาด `dim`
+    pub fn new(dim: usize, config: IndexConfig) -> Self {
+        Self {
+            dim,
+            config,
+            vectors: HashMap::new(),
+            postings: HashMap::new(),
+            placement: HashMap::new(),
+            next_posting: 0,
+            jobs: VecDeque::new(),
+            stats: UpdateStats::default(),
+        }
+    }
+
+    /// สร้าง index จากคู่ `(id, vector)` ทั้งหมด
+    pub fn build<I>(dim: usize, config: IndexConfig, vectors: I) -> Self
+    where
+        I: IntoIterator<Item = (VectorId, Vec<f32>)>,
+    {
+        let mut index = Self::new(dim, config);
+        for (id, v) in vectors {
+            assert_eq!(v.len(), dim, "vector {} has dimension {}, expected {}", id, v.len(), dim);
+            index.vectors.insert(id, v);
+        }
+        index.rebuild();
+        index
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+
+    /// จำนวน posting list ปัจจุบัน
+    pub fn posting_count(&self) -> usize {
+        self.postings.len()
+    }
+
+    /// จำนวนงาน split/merge ที่ยังค้างในคิว
+    pub fn pending_jobs(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn stats(&self) -> UpdateStats {
+        self.stats
+    }
+
+    /// สร้าง posting list ใหม่ทั้งหมดด้วย k-means จาก vector ที่มีอยู่
+    pub fn rebuild(&mut self) {
+        self.postings.clear();
+        self.placement.clear();
+        self.jobs.clear();
+        if self.vectors.is_empty() {
+            return;
+        }
+
+        let mut ids: Vec<VectorId> = self.vectors.keys().copied().collect();
+        ids.sort_unstable();
+
+        let k = ids.len().div_ceil(self.config.target_posting_size.max(1));
+        for centroid in kmeans(&self.vectors, &ids, k, self.config.kmeans_iterations) {
+            self.add_posting(centroid);
+        }
+
+        for id in ids {
+            self.place(id);
+        }
+
+        let empty: Vec<PostingId> = self
+            .postings
+            .iter()
+            .filter(|(_, p)| p.ids.is_empty())
+            .map(|(&pid, _)| pid)
+            .collect();
+        for pid in empty {
+            self.postings.remove(&pid);
+        }
+    }
+
+    /// เพิ่ม vector เข้า posting ที่ใกล้ที่สุด (รวม replica)
+    /// ถ้า posting ยาวเกิน `max_posting_size` จะเข้าคิว split ไว้ให้ background ทำ
+    pub fn insert(&mut self, id: VectorId, vector: Vec<f32>) {
+        assert_eq!(vector.len(), self.dim, "vector {} has dimension {}, expected {}", id, vector.len(), self.dim);
+
+        if self.vectors.insert(id, vector).is_some() {
+            // id ซ้ำ: เอาตำแหน่งเดิมออกก่อนแล้ววางใหม่ตาม vector ใหม่
+            self.unplace(id);
+        }
+
+        if self.postings.is_empty() {
+            let centroid = self.vectors[&id].clone();
+            self.add_posting(centroid);
+        }
+
+        for pid in self.place(id) {
+            if self.postings[&pid].ids.len() > self.config.max_posting_size {
+                self.enqueue(Job::Split(pid));
+            }
+        }
+    }
+
+    /// เอา vector `id` ออกจาก index คืน `false` ถ้าไม่มี id นี้
+    /// posting ที่เล็กลงจนต่ำกว่า `min_posting_size` จะถูก merge ใน background
+    pub fn remove(&mut self, id: VectorId) -> bool {
+        if self.vectors.remove(&id).is_none() {
+            return false;
+        }
+        let places = self.placement.get(&id).cloned().unwrap_or_default();
+        self.unplace(id);
+        for pid in places {
+            self.check_size(pid);
+        }
+        true
+    }
+
+    /// ทำงาน split/merge ที่ค้างอยู่ไม่เกิน `budget` งาน คืนจำนวนงานที่ทำ
+    pub fn process_jobs(&mut self, budget: usize) -> usize {
+        let mut done = 0;
+        while done < budget {
+            let Some(job) = self.jobs.pop_front() else {
+                break;
+            };
+            match job {
+                Job::Split(pid) => self.split(pid),
+                Job::Merge(pid) => self.merge(pid),
+            }
+            done += 1;
+        }
+        done
+    }
+
+    fn enqueue(&mut self, job: Job) {
+        if !self.jobs.contains(&job) {
+            self.jobs.push_back(job);
+        }
+    }
+
+    fn add_posting(&mut self, centroid: Vec<f32>) -> PostingId {
+        let pid = self.next_posting;
+        self.next_posting += 1;
+        self.postings.insert(pid, Posting { centroid, ids: Vec::new() });
+        pid
+    }
+
+    /// วาง vector ลง posting ตาม `assign` และบันทึก placement
+    fn place(&mut self, id: VectorId) -> Vec<PostingId> {
+        let targets = self.assign(&self.vectors[&id]);
+        for &pid in &targets {
+            if let Some(p) = self.postings.get_mut(&pid) {
+                p.ids.push(id);
+            }
+        }
+        self.placement.insert(id, targets.clone());
+        targets
+    }
+
+    /// เอา vector ออกจากทุก posting ที่มันอยู่
+    fn unplace(&mut self, id: VectorId) {
+        for pid in self.placement.remove(&id).unwrap_or_default() {
+            if let Some(p) = self.postings.get_mut(&pid) {
+                p.ids.retain(|&x| x != id);
+            }
+        }
+    }
+
+    /// แบ่ง posting ที่โตเกินเป็นสองด้วย 2-means แล้ว reassign vector รอบ ๆ
+    fn split(&mut self, pid: PostingId) {
+        let Some(posting) = self.postings.get(&pid) else {
+            return;
+        };
+        if posting.ids.len() <= self.config.max_posting_size {
+            return;
+        }
+
+        let old_centroid = posting.centroid.clone();
+        let members = posting.ids.clone();
+        let centroids = kmeans(&self.vectors, &members, 2, self.config.kmeans_iterations);
+        if centroids.len() < 2 {
+            return;
+        }
+
+        let sides: Vec<usize> = members.iter().map(|id| nearest(&centroids, &self.vectors[id])).collect();
+        if sides.iter().all(|&s| s == sides[0]) {
+            // vector เหมือนกันทั้งหมด แบ่งไม่ได้
+            return;
+        }
+
+        // posting ข้างเคียงของ centroid เดิม ต้องหาก่อนลบ posting เดิม
+        let neighbors = self.nearest_postings(&old_centroid, self.config.reassign_neighbors + 1);
+
+        self.postings.remove(&pid);
+        for id in &members {
+            if let Some(places) = self.placement.get_mut(id) {
+                places.retain(|&p| p != pid);
+            }
+        }
+
+        let new_ids: Vec<PostingId> = centroids.into_iter().map(|c| self.add_posting(c)).collect();
+        for (&id, &side) in members.iter().zip(&sides) {
+            let target = new_ids[side];
+            self.postings.get_mut(&target).unwrap().ids.push(id);
+            self.placement.entry(id).or_default().push(target);
+        }
+        self.stats.splits += 1;
+
+        // reassign: vector ใน posting ใหม่และ posting ข้างเคียงที่อาจมี centroid ใกล้กว่าเดิม
+        let mut candidates: Vec<VectorId> = members;
+        for n in neighbors.into_iter().filter(|&n| n != pid) {
+            if let Some(p) = self.postings.get(&n) {
+                candidates.extend(&p.ids);
+            }
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        for id in candidates {
+            self.reassign(id);
+        }
+
+        for pid in new_ids {
+            self.check_size(pid);
+        }
+    }
+
+    /// ย้าย vector ไป posting ที่ถูกต้องถ้า placement ปัจจุบันไม่ตรงกับ `assign`
+    fn reassign(&mut self, id: VectorId) {
+        let mut wanted = self.assign(&self.vectors[&id]);
+        let mut current = self.placement.get(&id).cloned().unwrap_or_default();
+        wanted.sort_unstable();
+        current.sort_unstable();
+        if wanted == current {
+            return;
+        }
+
+        self.unplace(id);
+        for pid in self.place(id) {
+            self.check_size(pid);
+        }
+        self.stats.reassigned += 1;
+    }
+
+    /// รวม posting ที่เล็กเกินเข้ากับ posting อื่น โดยวาง vector ใหม่ทีละตัว
+    fn merge(&mut self, pid: PostingId) {
+        if self.postings.len() <= 1 {
+            return;
+        }
+        let Some(posting) = self.postings.get(&pid) else {
+            return;
+        };
+        if posting.ids.len() >= self.config.min_posting_size {
+            return;
+        }
+
+        // ไม่ merge ถ้า posting ที่รับจะยาวเกิน max_posting_size (เช่น posting ของ outlier)
+        // ไม่งั้น posting ที่รับจะถูก split แยก vector กลุ่มเดิมออกมาอีก วนไม่จบ
+        let mut incoming: HashMap<PostingId, usize> = HashMap::new();
+        for id in &posting.ids {
+            for target in self.assign_except(&self.vectors[id], Some(pid)) {
+                if !self.postings[&target].ids.contains(id) {
+                    *incoming.entry(target).or_default() += 1;
+                }
+            }
+        }
+        if incoming
+            .iter()
+            .any(|(target, n)| self.postings[target].ids.len() + n > self.config.max_posting_size)
+        {
+            return;
+        }
+
+        let members = self.postings.remove(&pid).map(|p| p.ids).unwrap_or_default();
+        for &id in &members {
+            if let Some(places) = self.placement.get_mut(&id) {
+                places.retain(|&p| p != pid);
+            }
+        }
+        self.stats.merges += 1;
+
+        for id in members {
+            self.unplace(id);
+            for target in self.place(id) {
+                self.check_size(target);
+            }
+        }
+    }
+
+    fn check_size(&mut self, pid: PostingId) {
+        let Some(p) = self.postings.get(&pid) else {
+            return;
+        };
+        if p.ids.len() > self.config.max_posting_size {
+            self.enqueue(Job::Split(pid));
+        } else if p.ids.len() < self.config.min_posting_size {
+            self.enqueue(Job::Merge(pid));
+        }
+    }
+
+    /// posting ที่ centroid ใกล้ `vector` ที่สุด `n` อัน เรียงจากใกล้ไปไกล
+    fn nearest_postings(&self, vector: &[f32], n: usize) -> Vec<PostingId> {
+        let mut order: Vec<(f32, PostingId)> = self
+            .postings
+            .iter()
+            .map(|(&pid, p)| (l2_squared(vector, &p.centroid), pid))
+            .collect();
+        order.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        order.into_iter().take(n).map(|(_, pid)| pid).collect()
+    }
+
+    /// ค้นหา `top_k` vector ที่ใกล้ `query` ที่สุด โดย probe `nprobe` posting ที่ใกล้ที่สุด
+    /// คืนคู่ `(id, distance)` เรียงจากใกล้ไปไกล โดย distance คือ L2 ยกกำลังสอง
+    pub fn search(&self, query: &[f32], params: &SearchParams) -> Vec<(VectorId, f32)> {
+        self.scan(query, params, |_| true, false)
+    }
+
+    /// เหมือน [`search`](Self::search) แต่รับเฉพาะ vector ที่ `filter` คืน `true`
+    /// กรองระหว่างสแกน candidate และถ้าครบ `nprobe` posting แล้วยังได้ไม่ถึง `top_k`
+    /// จะ probe posting ที่ไกลขึ้นต่อไปเรื่อย ๆ จนครบ (หรือหมด index)
+    pub fn search_filtered(&self, query: &[f32], params: &SearchParams, filter: impl Fn(VectorId) -> bool) -> Vec<(VectorId, f32)> {
+        self.scan(query, params, filter, true)
+    }
+
+    fn scan(&self, query: &[f32], params: &SearchParams, filter: impl Fn(VectorId) -> bool, widen: bool) -> Vec<(VectorId, f32)> {
+        assert_eq!(query.len(), self.dim, "query has dimension {}, expected {}", query.len(), self.dim);
+        let k = params.top_k;
+        if k == 0 || self.postings.is_empty() {
+            return Vec::new();
+        }
+        let nprobe = params.nprobe.unwrap_or(self.config.default_nprobe).max(1);
+        let max_distance = params.max_distance.unwrap_or(f32::INFINITY);
+        let probe = if widen { self.postings.len() } else { nprobe };
+
+        let mut seen = HashSet::new();
+        let mut heap: BinaryHeap<Candidate> = BinaryHeap::with_capacity(k + 1);
+
+        for (probed, pid) in self.nearest_postings(query, probe).into_iter().enumerate() {
+            if probed >= nprobe && heap.len() >= k {
+                break;
+            }
+            for &id in &self.postings[&pid].ids {
+                if !seen.insert(id) || !filter(id) {
+                    continue;
+                }
+                let distance = l2_squared(query, &self.vectors[&id]);
+                if distance > max_distance {
+                    continue;
+                }
+                heap.push(Candidate { distance, id });
+                if heap.len() > k {
+                    heap.pop();
+                }
+            }
+        }
+
+        heap.into_sorted_vec().into_iter().map(|c| (c.id, c.distance)).collect()
+    }
+
+    /// หา posting ที่ vector ควรถูกใส่ (posting ที่ใกล้ที่สุด + boundary replica)
+    fn assign(&self, vector: &[f32]) -> Vec<PostingId> {
+        self.assign_except(vector, None)
+    }
+
+    /// เหมือน [`assign`](Self::assign) แต่ไม่นับ posting `skip`
+    fn assign_except(&self, vector: &[f32], skip: Option<PostingId>) -> Vec<PostingId> {
+        let mut dists: Vec<(f32, PostingId)> = self
+            .postings
+            .iter()
+            .filter(|&(&pid, _)| Some(pid) != skip)
+            .map(|(&pid, p)| (l2_squared(vector, &p.centroid), pid))
+            .collect();
+        dists.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+        let Some(&(nearest, _)) = dists.first() else {
+            return Vec::new();
+        };
+        let limit = nearest * (1.0 + self.config.replica_epsilon).powi(2);
+
+        dists
+            .into_iter()
+            .take(self.config.replica_count.max(1))
+            .enumerate()
+            .take_while(|&(rank, (d, _))| rank == 0 || d <= limit)
+            .map(|(_, (_, p))| p)
+            .collect()
+    }
+}
+
+/// เริ่ม thread ที่คอยทำงาน split/merge/reassign ที่ค้างใน index ทุก `interval`
+/// แต่ละรอบถือ write lock ทำงานครั้งละไม่เกิน `batch` งาน เพื่อไม่ให้ search รอนาน
+pub fn spawn_rebuilder(index: Arc<RwLock<Index>>, interval: Duration, batch: usize) -> JoinHandle<()> {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(interval);
+            let pending = index.read().unwrap().pending_jobs();
+            if pending == 0 {
+                continue;
+            }
+            let mut index = index.write().unwrap();
+            let done = index.process_jobs(batch);
+            let stats = index.stats();
+            println!(
+                "spfresh rebuilder: {} jobs done, {} pending ({} splits, {} merges, {} reassigned total)",
+                done,
+                index.pending_jobs(),
+                stats.splits,
+                stats.merges,
+                stats.reassigned
+            );
+        }
+    })
+}
+
+/// ผู้สมัครใน top-k heap เรียงตามระยะ (max-heap เพื่อ pop ตัวไกลสุดทิ้ง)
+#[derive(Clone, Copy, Debug)]
+struct Candidate {
+    distance: f32,
+    id: VectorId,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+/// ระยะ Euclidean ยกกำลังสอง
+pub fn l2_squared(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// k-means แบบ Lloyd เริ่มจาก centroid ที่เลือกแบบกระจายเท่า ๆ กันตาม id
+fn kmeans(vectors: &HashMap<VectorId, Vec<f32>>, ids: &[VectorId], k: usize, iterations: usize) -> Vec<Vec<f32>> {
+    let k = k.clamp(1, ids.len());
+    let dim = vectors[&ids[0]].len();
+
+    let mut centroids: Vec<Vec<f32>> = (0..k)
+        .map(|i| vectors[&ids[i * ids.len() / k]].clone())
+        .collect();
+
+    for _ in 0..iterations {
+        let mut sums = vec![vec![0.0f32; dim]; k];
+        let mut counts = vec![0usize; k];
+
+        for id in ids {
+            let v = &vectors[id];
+            let c = nearest(&centroids, v);
+            counts[c] += 1;
+            for (s, x) in sums[c].iter_mut().zip(v) {
+                *s += x;
+            }
+        }
+
+        let mut moved = false;
+        for c in 0..k {
+            if counts[c] == 0 {
+                continue;
+            }
+            let n = counts[c] as f32;
+            let updated: Vec<f32> = sums[c].iter().map(|s| s / n).collect();
+            if updated != centroids[c] {
+                moved = true;
+                centroids[c] = updated;
+            }
+        }
+
+        if !moved {
+            break;
+        }
+    }
+
+    centroids
+}
+
+fn nearest(centroids: &[Vec<f32>], v: &[f32]) -> usize {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (l2_squared(v, c), i))
+        .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)))
+        .map(|(_, i)| i)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> IndexConfig {
+        IndexConfig {
+            target_posting_size: 8,
+            max_posting_size: 16,
+            min_posting_size: 4,
+            ..IndexConfig::default()
+        }
+    }
+
+    /// จุดที่ไม่ซ้ำกันใน 4 กลุ่มที่แยกกันชัดเจน
+    fn point(i: VectorId) -> Vec<f32> {
+        let cluster = (i % 4) as f32;
+        let i = i / 4;
+        vec![cluster * 10.0 + (i % 17) as f32 * 0.3, cluster * 5.0 + (i / 17) as f32 * 0.3]
+    }
+
+    fn drain_jobs(index: &mut Index) {
+        for _ in 0..1000 {
+            if index.process_jobs(64) == 0 {
+                return;
+            }
+        }
+        panic!("{} jobs still pending", index.pending_jobs());
+    }
+
+    /// `placement` กับ id ใน posting ต้องตรงกันทั้งสองทาง และทุก vector ต้องอยู่ในอย่างน้อยหนึ่ง posting
+    fn assert_consistent(index: &Index) {
+        for (&pid, posting) in &index.postings {
+            let unique: HashSet<_> = posting.ids.iter().collect();
+            assert_eq!(unique.len(), posting.ids.len(), "posting {} has duplicate ids", pid);
+            for id in &posting.ids {
+                assert!(index.vectors.contains_key(id), "posting {} has removed vector {}", pid, id);
+                assert!(index.placement[id].contains(&pid), "placement of {} is missing posting {}", id, pid);
+            }
+        }
+        let placed: HashSet<_> = index.placement.keys().collect();
+        assert_eq!(placed, index.vectors.keys().collect());
+        for (id, places) in &index.placement {
+            assert!(!places.is_empty(), "vector {} is in no posting", id);
+            for pid in places {
+                assert!(index.postings[pid].ids.contains(id), "posting {} is missing vector {}", pid, id);
+            }
+        }
+    }
+
+    /// ทุก vector ที่ยังอยู่ต้องค้นเจอด้วย vector ของตัวเอง
+    fn assert_searchable(index: &Index) {
+        let params = SearchParams { top_k: 1, nprobe: Some(index.posting_count()), max_distance: None };
+        for (&id, vector) in &index.vectors {
+            assert_eq!(index.search(vector, &params), vec![(id, 0.0)]);
+        }
+    }
+
+    #[test]
+    fn filtered_search_widens_probes_until_top_k() {
+        let index = Index::build(2, config(), (0..200).map(|id| (id, point(id))));
+        assert!(index.posting_count() > 4);
+        let params = SearchParams { top_k: 5, nprobe: Some(1), max_distance: None };
+        let query = point(0);
+
+        // posting ที่ใกล้ที่สุดเป็นกลุ่มอื่นทั้งหมด กรองทีหลังจะไม่เหลืออะไร
+        let in_last_cluster = |id: VectorId| id % 4 == 3;
+        assert!(!index.search(&query, &params).into_iter().any(|(id, _)| in_last_cluster(id)));
+
+        let matches = index.search_filtered(&query, &params, in_last_cluster);
+        assert_eq!(matches.len(), 5);
+        assert!(matches.iter().all(|&(id, _)| in_last_cluster(id)));
+        assert!(matches.windows(2).all(|w| w[0].1 <= w[1].1));
+    }
+
+    #[test]
+    fn filter_is_applied_while_scanning() {
+        let index = Index::build(2, config(), (0..200).map(|id| (id, point(id))));
+        let params = SearchParams { top_k: 5, nprobe: Some(1), max_distance: None };
+        let query = point(0);
+        let checked = std::cell::RefCell::new(Vec::new());
+        let even = |id: VectorId| {
+            checked.borrow_mut().push(id);
+            id.is_multiple_of(2)
+        };
+
+        let matches = index.search_filtered(&query, &params, even);
+        assert_eq!(matches.len(), 5);
+        assert!(matches.iter().all(|&(id, _)| id.is_multiple_of(2)));
+        // แต่ละ id ถูกเช็คครั้งเดียวแม้ถูก replicate ไปหลาย posting และไม่ได้สแกนทั้ง index
+        let checked = checked.into_inner();
+        assert_eq!(checked.iter().collect::<HashSet<_>>().len(), checked.len());
+        assert!(checked.len() < index.len());
+
+        // probe ครบทุก posting ได้ผลเท่ากับเรียงระยะของทุก id ที่ผ่าน filter
+        let all = SearchParams { top_k: 5, nprobe: Some(index.posting_count()), max_distance: None };
+        let mut expected: Vec<(VectorId, f32)> =
+            (0..200).filter(|id: &VectorId| id.is_multiple_of(2)).map(|id| (id, l2_squared(&query, &point(id)))).collect();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        expected.truncate(5);
+        assert_eq!(index.search_filtered(&query, &all, |id| id.is_multiple_of(2)), expected);
+    }
+
+    #[test]
+    fn insert_splits_postings_past_max_size() {
+        let mut index = Index::new(2, config());
+        for id in 0..200 {
+            index.insert(id, point(id));
+        }
+        assert!(index.pending_jobs() > 0);
+
+        drain_jobs(&mut index);
+        assert!(index.stats().splits > 0);
+        assert!(index.posting_count() > 1);
+        assert!(index.postings.values().all(|p| p.ids.len() <= config().max_posting_size));
+        assert_consistent(&index);
+        assert_searchable(&index);
+    }
+
+    #[test]
+    fn remove_merges_postings_below_min_size() {
+        let mut index = Index::build(2, config(), (0..200).map(|id| (id, point(id))));
+        let postings = index.posting_count();
+        for id in (0..200).filter(|id| id % 10 != 0) {
+            assert!(index.remove(id));
+        }
+        assert!(!index.remove(1));
+        assert!(index.pending_jobs() > 0);
+
+        drain_jobs(&mut index);
+        assert!(index.stats().merges > 0);
+        assert!(index.posting_count() < postings);
+        assert_eq!(index.len(), 20);
+        assert_consistent(&index);
+        assert_searchable(&index);
+        let params = SearchParams { top_k: 200, nprobe: Some(index.posting_count()), max_distance: None };
+        assert!(index.search(&point(1), &params).iter().all(|(id, _)| id % 10 == 0));
+    }
+
+    #[test]
+    fn reinserted_outlier_settles_in_its_own_posting() {
+        let mut index = Index::build(2, config(), (0..64).map(|id| (id, point(id))));
+        index.insert(5, vec![100.0, 100.0]);
+        drain_jobs(&mut index);
+
+        assert_eq!(index.len(), 64);
+        assert_consistent(&index);
+        let params = SearchParams { top_k: 1, ..SearchParams::default() };
+        assert_eq!(index.search(&[100.0, 100.0], &params), vec![(5, 0.0)]);
+        assert_eq!(index.placement[&5].len(), 1);
+        assert_eq!(index.postings[&index.placement[&5][0]].ids, vec![5]);
+    }
 }
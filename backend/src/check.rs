@@ -0,0 +1,243 @@
+//! ตรวจว่า `reviews.index` กับ `reviews.jsonl` ตรงกัน และสร้างไฟล์ vector ใหม่จาก metadata
+//!
+//! ```text
+//! backend check           ตรวจไฟล์แล้วรายงานปัญหา (exit 1 ถ้ามีปัญหา)
+//! backend rebuild-index   embed metadata ทุกบรรทัดใหม่แล้วเขียนไฟล์คู่ใหม่ทั้งหมด
+//! ```
+//!
+//! ต้องหยุด server ก่อนรัน `rebuild-index` เพราะไฟล์จะถูกแทนที่
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use fastembed::{ModelId, TextEmbedding};
+
+use serde::Deserialize;
+
+use crate::offset_index::{self, Entry};
+use crate::store::{ReviewId, ReviewStore};
+use crate::text;
+use crate::vector_file::{self, HEADER_LEN};
+use crate::{Review, StoredReview};
+
+/// บรรทัด metadata ที่อาจยังไม่มี id (ไฟล์จากก่อนมี review id)
+#[derive(Deserialize)]
+struct LegacyReview {
+    id: Option<ReviewId>,
+    version: Option<u32>,
+    #[serde(flatten)]
+    review: Review,
+}
+
+/// ผลการตรวจไฟล์คู่
+#[derive(Debug, Default)]
+pub struct Report {
+    /// จำนวน vector ตาม header (`None` ถ้าอ่าน header ไม่ได้)
+    pub vectors: Option<u64>,
+    /// จำนวนบรรทัดที่สมบูรณ์ (ลงท้ายด้วย `\n`) ใน metadata
+    pub metadata_lines: u64,
+    pub valid_reviews: u64,
+    pub issues: Vec<String>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn print(&self) {
+        match self.vectors {
+            Some(n) => println!("vectors:        {}", n),
+            None => println!("vectors:        (unreadable)"),
+        }
+        println!("metadata lines: {}", self.metadata_lines);
+        println!("valid reviews:  {}", self.valid_reviews);
+        if self.is_ok() {
+            println!("✅ index and metadata agree");
+        } else {
+            println!("❌ {} issue(s):", self.issues.len());
+            for issue in &self.issues {
+                println!("  - {}", issue);
+            }
+        }
+    }
+}
+
+/// ตรวจไฟล์คู่แบบอ่านอย่างเดียว
+pub fn check(vector_path: &str, metadata_path: &str, dim: usize, model: ModelId) -> io::Result<Report> {
+    let mut report = Report::default();
+
+    match vector_file::inspect(vector_path) {
+        Ok((header, len)) => {
+            report.vectors = Some(header.count);
+            if header.dim as usize != dim {
+                report.issues.push(format!(
+                    "{}: vectors have {} dims but the embedding model produces {}",
+                    vector_path, header.dim, dim
+                ));
+            }
+            if header.model.kind != 0 && header.model != model {
+                report.issues.push(format!(
+                    "{}: vectors were made by the {} but the server is using the {}",
+                    vector_path, header.model, model
+                ));
+            }
+            let expected = HEADER_LEN + header.count * header.record_len();
+            if len > expected {
+                report.issues.push(format!(
+                    "{}: {} uncommitted bytes at offset {} (dropped on next start)",
+                    vector_path,
+                    len - expected,
+                    expected
+                ));
+            } else if len < expected {
+                report.issues.push(format!(
+                    "{}: file is {} bytes but {} vectors need {} bytes (missing from offset {})",
+                    vector_path, len, header.count, expected, len
+                ));
+            }
+        }
+        Err(e) => report.issues.push(format!("{}: {}", vector_path, e)),
+    }
+
+    let vector_ids = vector_file::read_ids(vector_path).unwrap_or_default();
+    let sidecar = offset_index::sidecar_path(Path::new(metadata_path));
+    let offsets = offset_index::read(&sidecar).unwrap_or_default();
+
+    let mut reader = BufReader::new(File::open(metadata_path)?);
+    let mut offset = 0u64;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let n = reader.read_line(&mut line)?;
+        if n == 0 {
+            break;
+        }
+        if !line.ends_with('\n') {
+            report.issues.push(format!(
+                "{}: unterminated line at offset {} ({} bytes, dropped on next start)",
+                metadata_path, offset, n
+            ));
+            break;
+        }
+
+        let line_no = report.metadata_lines;
+        report.metadata_lines += 1;
+        match serde_json::from_str::<StoredReview>(line.trim_end()) {
+            Ok(stored) => {
+                report.valid_reviews += 1;
+                if let Some(&vector_id) = vector_ids.get(line_no as usize)
+                    && vector_id != stored.id
+                {
+                    report.issues.push(format!(
+                        "{}: line {} at offset {} has id {} but vector {} has id {}",
+                        metadata_path, line_no, offset, stored.id, line_no, vector_id
+                    ));
+                }
+                let expected = Entry {
+                    id: stored.id,
+                    offset,
+                    len: n as u64,
+                };
+                if let Some(&entry) = offsets.get(line_no as usize)
+                    && entry != expected
+                {
+                    report.issues.push(format!(
+                        "{}: entry {} points to id {} at offset {} but line {} is id {} at offset {} (delete the file to rebuild it on next start)",
+                        sidecar.display(),
+                        line_no,
+                        entry.id,
+                        entry.offset,
+                        line_no,
+                        stored.id,
+                        offset
+                    ));
+                }
+            }
+            Err(e) => report.issues.push(format!(
+                "{}: line {} at offset {} is not a valid review with id: {}",
+                metadata_path, line_no, offset, e
+            )),
+        }
+        offset += n as u64;
+    }
+
+    if let Some(vectors) = report.vectors
+        && vectors != report.metadata_lines
+    {
+        report.issues.push(format!(
+            "{} has {} vectors but {} has {} lines",
+            vector_path, vectors, metadata_path, report.metadata_lines
+        ));
+    }
+
+    Ok(report)
+}
+
+/// embed ทุก review ที่ parse ได้ใน metadata ใหม่ แล้วแทนที่ไฟล์คู่เดิม
+/// ไฟล์เดิมถูกเก็บไว้เป็น `.bak` บรรทัดที่ parse ไม่ได้จะถูกข้ามและรายงาน
+/// review ที่มี id อยู่แล้วคง id และ version เดิม ที่ยังไม่มี id (หรือ id กับ version ซ้ำ) จะได้ id ใหม่
+pub fn rebuild(vector_path: &str, metadata_path: &str, embedder: &TextEmbedding) -> io::Result<u64> {
+    let new_vector_path = format!("{}.rebuild", vector_path);
+    let new_metadata_path = format!("{}.rebuild", metadata_path);
+    let offsets_path = offset_index::sidecar_path(Path::new(metadata_path));
+    let new_offsets_path = offset_index::sidecar_path(Path::new(&new_metadata_path));
+    for path in [Path::new(&new_vector_path), Path::new(&new_metadata_path), &new_offsets_path] {
+        if fs::metadata(path).is_ok() {
+            fs::remove_file(path)?;
+        }
+    }
+
+    let (mut store, _) = ReviewStore::open(&new_vector_path, &new_metadata_path, embedder.dim(), embedder.model_id())?;
+    let reader = BufReader::new(File::open(metadata_path)?);
+    let mut skipped = 0;
+    let mut latest: HashMap<ReviewId, u32> = HashMap::new();
+    let mut legacy = Vec::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let LegacyReview { id, version, review } = match serde_json::from_str::<LegacyReview>(&line) {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("Skipping line {}: {}", i, e);
+                skipped += 1;
+                continue;
+            }
+        };
+        let combined = format!("{} {}", review.review_title, review.review_body);
+        let embedding = embedder
+            .embed(&text::preprocess(&combined))
+            .map_err(|e| io::Error::other(format!("line {}: {}", i, e)))?;
+        let version = version.unwrap_or(1);
+        match id {
+            Some(id) if latest.get(&id).is_none_or(|&v| version > v) => {
+                latest.insert(id, version);
+                store.append_stored(&StoredReview { id, version, review }, &embedding)?;
+            }
+            // ยังไม่มี id: แจกทีหลังเพื่อไม่ให้ชนกับ id ที่มีอยู่แล้วในบรรทัดถัด ๆ ไป
+            _ => legacy.push((review, embedding)),
+        }
+    }
+    for (review, embedding) in legacy {
+        store.append(&review, &embedding)?;
+    }
+    let written = store.len();
+    drop(store);
+
+    for path in [vector_path, metadata_path] {
+        if fs::metadata(path).is_ok() {
+            fs::rename(path, format!("{}.bak", path))?;
+        }
+    }
+    if fs::metadata(&offsets_path).is_ok() {
+        fs::remove_file(&offsets_path)?;
+    }
+    fs::rename(&new_vector_path, vector_path)?;
+    fs::rename(&new_metadata_path, metadata_path)?;
+    fs::rename(&new_offsets_path, &offsets_path)?;
+
+    println!("Rebuilt {} reviews ({} lines skipped), old files kept as .bak", written, skipped);
+    Ok(written)
+}
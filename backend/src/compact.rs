@@ -0,0 +1,315 @@
+//! compaction: เขียนไฟล์คู่ generation ใหม่ที่เหลือเฉพาะ version ล่าสุดของ review ที่ยังไม่ถูกลบ
+//!
+//! ```text
+//! backend compact   compact ไฟล์ใน data/ (หยุด server ก่อน)
+//! ```
+//!
+//! หรือให้ server compact เองใน background ด้วย env `COMPACTION_INTERVAL_SECS`
+//!
+//! ขั้นตอน:
+//!
+//! 1. อ่าน record ที่ commit แล้วจากไฟล์ปัจจุบันแบบอ่านอย่างเดียว แล้วเขียนลง `<path>.compact`
+//!    (ไม่ต้องถือ lock ของ store เพราะ record ที่ commit แล้วไม่ถูกแก้อีก)
+//! 2. สร้าง marker `<metadata>.compacting` เป็น commit point ของการสลับไฟล์
+//! 3. rename ไฟล์ `.compact` ทับไฟล์เดิมทีละไฟล์ แล้วลบ marker
+//!
+//! ถ้า process ตายระหว่างทาง [`recover`] ตอนเปิด store ครั้งถัดไปจะ rename ต่อให้ครบถ้ามี marker
+//! หรือลบไฟล์ `.compact` ทิ้งถ้ายังไม่มี marker จึงได้ไฟล์คู่ generation เดิมหรือใหม่ทั้งคู่เสมอ
+//!
+//! version เก่าที่ถูกแทนที่และ review ที่ถูกลบจะหายจากไฟล์จริง (history ของ version เก่าหายด้วย)
+//! tombstone ยังเก็บไว้ และ id ถัดไปที่จะแจกไม่ถอยหลัง
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::StoredReview;
+use crate::offset_index;
+use crate::store::{ReviewId, ReviewStore};
+use crate::vector_file;
+
+/// ผลของการ compact หนึ่งครั้ง
+#[derive(Debug, Default)]
+pub struct Report {
+    /// จำนวน record ก่อน compact
+    pub before: u64,
+    /// จำนวน record ที่เขียนลง generation ใหม่
+    pub kept: u64,
+    /// version เก่าที่ถูกแทนที่แล้ว
+    pub superseded: u64,
+    /// record ของ review ที่ถูกลบ
+    pub deleted: u64,
+}
+
+impl Report {
+    pub fn print(&self) {
+        println!(
+            "Compacted {} records into {} ({} superseded versions, {} deleted records dropped)",
+            self.before, self.kept, self.superseded, self.deleted
+        );
+    }
+}
+
+fn compact_path(path: &str) -> String {
+    format!("{}.compact", path)
+}
+
+fn marker_path(metadata_path: &str) -> String {
+    format!("{}.compacting", metadata_path)
+}
+
+/// ไฟล์ปลายทางและไฟล์ `.compact` ที่จะ rename ทับ
+fn renames(vector_path: &str, metadata_path: &str) -> [(PathBuf, PathBuf); 3] {
+    let metadata = Path::new(metadata_path);
+    let compact_metadata = compact_path(metadata_path);
+    [
+        (PathBuf::from(compact_path(vector_path)), PathBuf::from(vector_path)),
+        (offset_index::sidecar_path(Path::new(&compact_metadata)), offset_index::sidecar_path(metadata)),
+        (PathBuf::from(&compact_metadata), metadata.to_path_buf()),
+    ]
+}
+
+fn sync_dir(path: &str) -> io::Result<()> {
+    let dir = Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    File::open(dir)?.sync_all()
+}
+
+/// เขียน generation ใหม่ลงไฟล์ `.compact` จาก `count` record แรกของไฟล์คู่ปัจจุบัน
+/// โดยอ่านอย่างเดียว คืน store ของไฟล์ใหม่ไว้ให้ต่อท้าย record ที่เพิ่มระหว่างนี้ก่อน [`swap`]
+pub fn write_generation(
+    vector_path: &str,
+    metadata_path: &str,
+    dim: usize,
+    count: u64,
+    is_deleted: impl Fn(ReviewId) -> bool,
+) -> io::Result<(ReviewStore, Report)> {
+    let (header, _) = vector_file::inspect(vector_path)?;
+    let vectors = vector_file::read_records(vector_path, count)?;
+
+    let mut records = Vec::with_capacity(vectors.len());
+    let reader = BufReader::new(File::open(metadata_path)?);
+    for ((vector_id, vector), line) in vectors.into_iter().zip(reader.lines()) {
+        let stored: StoredReview = serde_json::from_str(&line?)?;
+        if stored.id != vector_id {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("record {} has id {} in metadata but {} in vectors, run `backend check`", records.len(), stored.id, vector_id),
+            ));
+        }
+        records.push((stored, vector));
+    }
+    if (records.len() as u64) < count {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has fewer than {} lines", metadata_path, count),
+        ));
+    }
+
+    // record หลังสุดของแต่ละ id คือ version ปัจจุบัน
+    let latest: HashMap<ReviewId, usize> = records.iter().enumerate().map(|(i, (s, _))| (s.id, i)).collect();
+
+    let new_vector_path = compact_path(vector_path);
+    let new_metadata_path = compact_path(metadata_path);
+    discard(vector_path, metadata_path)?;
+    let (mut store, _) = ReviewStore::open(&new_vector_path, &new_metadata_path, dim, header.model)?;
+
+    let mut report = Report {
+        before: count,
+        ..Report::default()
+    };
+    for (i, (stored, vector)) in records.into_iter().enumerate() {
+        if latest[&stored.id] != i {
+            report.superseded += 1;
+        } else if is_deleted(stored.id) {
+            report.deleted += 1;
+        } else {
+            store.append_stored(&stored, &vector)?;
+            report.kept += 1;
+        }
+    }
+    store.reserve_ids(header.next_id)?;
+
+    Ok((store, report))
+}
+
+/// สลับไฟล์ `.compact` เข้าแทนไฟล์คู่เดิม (ต้องปิด store ของไฟล์ใหม่และหยุดเขียนไฟล์เดิมก่อน)
+pub fn swap(vector_path: &str, metadata_path: &str) -> io::Result<()> {
+    let marker = marker_path(metadata_path);
+    File::create(&marker)?.sync_all()?;
+    sync_dir(metadata_path)?;
+    finish(vector_path, metadata_path)
+}
+
+fn finish(vector_path: &str, metadata_path: &str) -> io::Result<()> {
+    for (from, to) in renames(vector_path, metadata_path) {
+        if fs::metadata(&from).is_ok() {
+            fs::rename(&from, &to)?;
+        }
+    }
+    sync_dir(metadata_path)?;
+    fs::remove_file(marker_path(metadata_path))?;
+    sync_dir(metadata_path)
+}
+
+fn discard(vector_path: &str, metadata_path: &str) -> io::Result<()> {
+    for (from, _) in renames(vector_path, metadata_path) {
+        if fs::metadata(&from).is_ok() {
+            fs::remove_file(&from)?;
+        }
+    }
+    Ok(())
+}
+
+/// เก็บกวาด compaction ที่ค้างอยู่ ต้องเรียกก่อนเปิด store
+/// คืน `true` ถ้าสลับไฟล์ที่ค้างอยู่จนเสร็จ
+pub fn recover(vector_path: &str, metadata_path: &str) -> io::Result<bool> {
+    if fs::metadata(marker_path(metadata_path)).is_ok() {
+        finish(vector_path, metadata_path)?;
+        Ok(true)
+    } else {
+        discard(vector_path, metadata_path)?;
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Review;
+    use fastembed::ModelId;
+
+    const MODEL: ModelId = ModelId { kind: ModelId::HASHING, fingerprint: 0 };
+
+    struct Paths {
+        dir: PathBuf,
+        vectors: String,
+        metadata: String,
+    }
+
+    fn temp_paths(name: &str) -> Paths {
+        let dir = std::env::temp_dir().join(format!("backend-compact-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Paths {
+            vectors: dir.join("reviews.index").to_str().unwrap().to_string(),
+            metadata: dir.join("reviews.jsonl").to_str().unwrap().to_string(),
+            dir,
+        }
+    }
+
+    fn review(title: &str) -> Review {
+        Review {
+            review_title: title.to_string(),
+            review_body: String::new(),
+            product_id: "P1".to_string(),
+            review_rating: 5,
+        }
+    }
+
+    fn open(paths: &Paths) -> ReviewStore {
+        ReviewStore::open(&paths.vectors, &paths.metadata, 2, MODEL).unwrap().0
+    }
+
+    fn title(store: &ReviewStore, id: ReviewId) -> Option<String> {
+        store.get(id).unwrap().map(|s| s.review.review_title)
+    }
+
+    /// review 1 มีสอง version, review 2 ถูกลบ, review 3 ไม่เปลี่ยน
+    fn write_history(paths: &Paths) -> [ReviewId; 3] {
+        let mut store = open(paths);
+        let edited = store.append(&review("old"), &[1.0, 0.0]).unwrap();
+        let deleted = store.append(&review("deleted"), &[0.0, 1.0]).unwrap();
+        let kept = store.append(&review("kept"), &[1.0, 1.0]).unwrap();
+        store.append_version(edited, &review("new"), &[0.5, 0.5]).unwrap();
+        [edited, deleted, kept]
+    }
+
+    fn compact(paths: &Paths, deleted: ReviewId) -> Report {
+        let count = vector_file::inspect(&paths.vectors).unwrap().0.count;
+        let (store, report) = write_generation(&paths.vectors, &paths.metadata, 2, count, |id| id == deleted).unwrap();
+        drop(store);
+        report
+    }
+
+    fn assert_compacted(paths: &Paths, [edited, deleted, kept]: [ReviewId; 3]) {
+        let mut store = open(paths);
+        assert_eq!(store.len(), 2);
+        assert_eq!(title(&store, edited).as_deref(), Some("new"));
+        assert_eq!(store.history(edited).unwrap().len(), 1);
+        assert_eq!(store.get(edited).unwrap().unwrap().version, 2);
+        assert_eq!(title(&store, deleted), None);
+        assert_eq!(title(&store, kept).as_deref(), Some("kept"));
+        assert_eq!(store.read_vectors().unwrap(), vec![(kept, vec![1.0, 1.0]), (edited, vec![0.5, 0.5])]);
+        // id ของ review ที่ถูกตัดทิ้งไม่ถูกแจกซ้ำ
+        assert_eq!(store.vectors().next_id(), kept + 1);
+    }
+
+    #[test]
+    fn compaction_keeps_only_latest_versions_of_live_reviews() {
+        let paths = temp_paths("swap");
+        let ids = write_history(&paths);
+        let report = compact(&paths, ids[1]);
+        assert_eq!((report.before, report.kept, report.superseded, report.deleted), (4, 2, 1, 1));
+
+        swap(&paths.vectors, &paths.metadata).unwrap();
+        assert!(fs::metadata(marker_path(&paths.metadata)).is_err());
+        assert!(!recover(&paths.vectors, &paths.metadata).unwrap());
+        assert_compacted(&paths, ids);
+        fs::remove_dir_all(&paths.dir).unwrap();
+    }
+
+    #[test]
+    fn recover_discards_a_generation_written_before_the_swap() {
+        let paths = temp_paths("before-swap");
+        let [edited, deleted, kept] = write_history(&paths);
+        compact(&paths, deleted);
+
+        // process ตายก่อนสร้าง marker
+        assert!(!recover(&paths.vectors, &paths.metadata).unwrap());
+        for (from, _) in renames(&paths.vectors, &paths.metadata) {
+            assert!(fs::metadata(&from).is_err(), "{} was not removed", from.display());
+        }
+        let store = open(&paths);
+        assert_eq!(store.len(), 4);
+        assert_eq!(store.history(edited).unwrap().len(), 2);
+        assert_eq!(title(&store, deleted).as_deref(), Some("deleted"));
+        assert_eq!(title(&store, kept).as_deref(), Some("kept"));
+        fs::remove_dir_all(&paths.dir).unwrap();
+    }
+
+    #[test]
+    fn recover_finishes_a_swap_interrupted_after_the_marker() {
+        let paths = temp_paths("during-swap");
+        let ids = write_history(&paths);
+        compact(&paths, ids[1]);
+
+        // process ตายหลังสร้าง marker และ rename ไปแล้วแค่ไฟล์ vector
+        File::create(marker_path(&paths.metadata)).unwrap();
+        let [(from, to), ..] = renames(&paths.vectors, &paths.metadata);
+        fs::rename(from, to).unwrap();
+
+        assert!(recover(&paths.vectors, &paths.metadata).unwrap());
+        assert!(fs::metadata(marker_path(&paths.metadata)).is_err());
+        assert_compacted(&paths, ids);
+        fs::remove_dir_all(&paths.dir).unwrap();
+    }
+
+    #[test]
+    fn recover_removes_a_marker_left_after_all_renames() {
+        let paths = temp_paths("after-swap");
+        let ids = write_history(&paths);
+        compact(&paths, ids[1]);
+
+        // process ตายหลัง rename ครบแต่ก่อนลบ marker
+        File::create(marker_path(&paths.metadata)).unwrap();
+        for (from, to) in renames(&paths.vectors, &paths.metadata) {
+            fs::rename(from, to).unwrap();
+        }
+
+        assert!(recover(&paths.vectors, &paths.metadata).unwrap());
+        assert!(fs::metadata(marker_path(&paths.metadata)).is_err());
+        assert_compacted(&paths, ids);
+        fs::remove_dir_all(&paths.dir).unwrap();
+    }
+}
@@ -0,0 +1,296 @@
+//! ตรวจ review ซ้ำตอน insert
+//!
+//! ซ้ำแบบ exact คือ title, body และ product_id ตรงกันทุกตัวอักษร index ใน memory เก็บแค่ hash ของเนื้อหา
+//! review ที่ hash ตรงจึงต้องอ่านมาเทียบเนื้อหาจริงก่อน (ดู [`ContentIndex::candidates`])
+//! ซ้ำแบบ near คือ embedding ใกล้กับ review ที่มีอยู่จน score (`1 / (1 + distance)` แบบเดียวกับ search)
+//! ถึงเกณฑ์ที่ขอ ซึ่งหาผ่าน spfresh index การตรวจไม่ได้ล็อกข้าม request
+//! review ซ้ำที่ส่งมาพร้อมกันใน request ต่างกันจึงอาจผ่านทั้งคู่
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use spfresh::l2_squared;
+
+use crate::Review;
+use crate::store::ReviewId;
+
+/// สิ่งที่ทำกับ review ที่ซ้ำ
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateAction {
+    /// ไม่เขียน และตอบ error (`409`)
+    Reject,
+    /// ไม่เขียน แต่ไม่นับเป็น error
+    Skip,
+    /// เขียนตามปกติ และรายงานว่าซ้ำกับ review ไหน
+    Flag,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateKind {
+    Exact,
+    Near,
+}
+
+/// review ที่มีอยู่แล้วซึ่ง review ที่ส่งมาซ้ำด้วย
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct DuplicateMatch {
+    pub id: ReviewId,
+    pub kind: DuplicateKind,
+    /// 1 สำหรับ exact
+    pub score: f32,
+}
+
+impl std::fmt::Display for DuplicateMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            DuplicateKind::Exact => write!(f, "review {} (exact)", self.id),
+            DuplicateKind::Near => write!(f, "review {} (near, score {:.3})", self.id, self.score),
+        }
+    }
+}
+
+fn content_hash(review: &Review) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content(review).hash(&mut hasher);
+    hasher.finish()
+}
+
+fn content(review: &Review) -> (&str, &str, &str) {
+    (&review.review_title, &review.review_body, &review.product_id)
+}
+
+/// เนื้อหาที่ใช้ตัดสินว่าซ้ำแบบ exact ตรงกันหรือไม่
+pub fn same_content(a: &Review, b: &Review) -> bool {
+    content(a) == content(b)
+}
+
+/// hash ของเนื้อหา → id ของ review ที่ยังไม่ถูกลบ
+#[derive(Default)]
+pub struct ContentIndex {
+    by_hash: HashMap<u64, Vec<ReviewId>>,
+}
+
+impl ContentIndex {
+    pub fn insert(&mut self, id: ReviewId, review: &Review) {
+        let ids = self.by_hash.entry(content_hash(review)).or_default();
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    pub fn remove(&mut self, id: ReviewId, review: &Review) {
+        let hash = content_hash(review);
+        if let Some(ids) = self.by_hash.get_mut(&hash) {
+            ids.retain(|&other| other != id);
+            if ids.is_empty() {
+                self.by_hash.remove(&hash);
+            }
+        }
+    }
+
+    /// id ที่ hash ของเนื้อหาตรงกับ `review` เรียงจากเก่าไปใหม่ hash อาจชนกันได้
+    /// ผู้เรียกต้องเทียบเนื้อหาด้วย [`same_content`]
+    pub fn candidates(&self, review: &Review) -> Vec<ReviewId> {
+        let mut ids = self.by_hash.get(&content_hash(review)).cloned().unwrap_or_default();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+/// review ที่จะถูกเขียนพร้อมกันใน bulk แบบ atomic ซึ่งยังไม่อยู่ใน index
+/// ใช้หา review ที่ซ้ำกันเองใน request เดียวกัน
+#[derive(Default)]
+struct PendingReviews {
+    /// เนื้อหา (title, body, product_id) → ลำดับใน request
+    by_content: HashMap<(String, String, String), usize>,
+    embeddings: Vec<(usize, Vec<f32>)>,
+}
+
+impl PendingReviews {
+    fn insert(&mut self, index: usize, review: &Review, embedding: &[f32]) {
+        let (title, body, product_id) = content(review);
+        self.by_content.entry((title.to_string(), body.to_string(), product_id.to_string())).or_insert(index);
+        self.embeddings.push((index, embedding.to_vec()));
+    }
+
+    /// ลำดับของ review ก่อนหน้าที่ซ้ำ ชนิด และ score (near ต้องได้ระยะไม่เกิน `max_distance`)
+    fn find(&self, review: &Review, embedding: &[f32], max_distance: Option<f32>) -> Option<(usize, DuplicateKind, f32)> {
+        let (title, body, product_id) = content(review);
+        let key = (title.to_string(), body.to_string(), product_id.to_string());
+        if let Some(&index) = self.by_content.get(&key) {
+            return Some((index, DuplicateKind::Exact, 1.0));
+        }
+        let max_distance = max_distance?;
+        self.embeddings
+            .iter()
+            .map(|(index, other)| (*index, l2_squared(embedding, other)))
+            .filter(|&(_, distance)| distance <= max_distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, distance)| (index, DuplicateKind::Near, 1.0 / (1.0 + distance)))
+    }
+}
+
+/// review ที่รายการใน bulk ซ้ำด้วย รายการก่อนหน้าใน request ยังไม่มี id จนกว่าจะเขียนเสร็จ
+enum Target {
+    Stored(DuplicateMatch),
+    Pending(usize, DuplicateKind, f32),
+}
+
+/// ผลการตรวจ bulk แบบ atomic ก่อนเขียน
+pub struct BulkPlan {
+    /// ลำดับของรายการที่ต้องเขียน
+    pub write: Vec<usize>,
+    found: Vec<(usize, Target)>,
+}
+
+/// ตรวจ review ซ้ำใน bulk แบบ atomic ทั้งกับ review ที่มีอยู่ (ผ่าน `find_stored`)
+/// และกับรายการก่อนหน้าใน request เดียวกัน ถ้า `action` เป็น reject และมีรายการซ้ำจะตอบ `409`
+pub fn plan_bulk(
+    reviews: &[Review],
+    embeddings: &[Vec<f32>],
+    action: DuplicateAction,
+    max_distance: Option<f32>,
+    mut find_stored: impl FnMut(&Review, &[f32]) -> Result<Option<DuplicateMatch>, (StatusCode, String)>,
+) -> Result<BulkPlan, (StatusCode, String)> {
+    let mut pending = PendingReviews::default();
+    let mut plan = BulkPlan { write: Vec::with_capacity(reviews.len()), found: Vec::new() };
+    for (index, (review, embedding)) in reviews.iter().zip(embeddings).enumerate() {
+        let target = match find_stored(review, embedding)? {
+            Some(duplicate_of) => Some(Target::Stored(duplicate_of)),
+            None => pending
+                .find(review, embedding, max_distance)
+                .map(|(earlier, kind, score)| Target::Pending(earlier, kind, score)),
+        };
+        match (action, target) {
+            (DuplicateAction::Reject, Some(target)) => {
+                let duplicate_of = match target {
+                    Target::Stored(duplicate_of) => duplicate_of.to_string(),
+                    Target::Pending(earlier, _, _) => format!("review #{} in this request", earlier),
+                };
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("Review #{} is a duplicate of {} (nothing was inserted)", index, duplicate_of),
+                ));
+            }
+            (DuplicateAction::Skip, Some(target)) => plan.found.push((index, target)),
+            (_, target) => {
+                plan.found.extend(target.map(|target| (index, target)));
+                pending.insert(index, review, embedding);
+                plan.write.push(index);
+            }
+        }
+    }
+    Ok(plan)
+}
+
+impl BulkPlan {
+    /// รายการที่ซ้ำพร้อม review ที่ซ้ำด้วย `ids` คือ id ตามลำดับใน request หลังเขียนแล้ว
+    pub fn duplicates(self, ids: &[Option<ReviewId>]) -> Vec<(usize, DuplicateMatch)> {
+        self.found
+            .into_iter()
+            .map(|(index, target)| {
+                let duplicate_of = match target {
+                    Target::Stored(duplicate_of) => duplicate_of,
+                    Target::Pending(earlier, kind, score) => DuplicateMatch {
+                        id: ids[earlier].expect("review matched within a request is always written"),
+                        kind,
+                        score,
+                    },
+                };
+                (index, duplicate_of)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(title: &str, product_id: &str) -> Review {
+        Review {
+            review_title: title.to_string(),
+            review_body: "body".to_string(),
+            product_id: product_id.to_string(),
+            review_rating: 5,
+        }
+    }
+
+    /// review ที่มีอยู่แล้ว: id 10 มีเนื้อหา "stored" ตรวจเฉพาะ exact
+    fn find_stored(review: &Review, _: &[f32]) -> Result<Option<DuplicateMatch>, (StatusCode, String)> {
+        let stored = self::review("stored", "P1");
+        Ok(same_content(review, &stored).then_some(DuplicateMatch { id: 10, kind: DuplicateKind::Exact, score: 1.0 }))
+    }
+
+    fn plan(reviews: &[Review], action: DuplicateAction) -> Result<BulkPlan, (StatusCode, String)> {
+        let embeddings: Vec<Vec<f32>> = (0..reviews.len()).map(|i| vec![i as f32 * 10.0, 0.0]).collect();
+        plan_bulk(reviews, &embeddings, action, None, find_stored)
+    }
+
+    fn matched(duplicates: &[(usize, DuplicateMatch)]) -> Vec<(usize, ReviewId)> {
+        duplicates.iter().map(|(index, m)| (*index, m.id)).collect()
+    }
+
+    #[test]
+    fn content_index_matches_only_identical_content() {
+        let mut index = ContentIndex::default();
+        index.insert(3, &review("a", "P1"));
+        index.insert(1, &review("a", "P1"));
+        index.insert(2, &review("a", "P2"));
+        assert_eq!(index.candidates(&review("a", "P1")), [1, 3]);
+        assert!(index.candidates(&review("A", "P1")).is_empty());
+
+        index.remove(1, &review("a", "P1"));
+        assert_eq!(index.candidates(&review("a", "P1")), [3]);
+        assert!(same_content(&review("a", "P1"), &Review { review_rating: 1, ..review("a", "P1") }));
+        assert!(!same_content(&review("a", "P1"), &review("a", "P2")));
+    }
+
+    #[test]
+    fn reject_fails_the_whole_bulk() {
+        let (status, message) = plan(&[review("new", "P1"), review("stored", "P1")], DuplicateAction::Reject).err().unwrap();
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(message.starts_with("Review #1 is a duplicate of review 10 (exact)"), "{}", message);
+
+        let reviews = [review("new", "P1"), review("other", "P1"), review("new", "P1")];
+        let (status, message) = plan(&reviews, DuplicateAction::Reject).err().unwrap();
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(message.contains("review #0 in this request"), "{}", message);
+
+        assert_eq!(plan(&reviews[..2], DuplicateAction::Reject).unwrap().write, [0, 1]);
+    }
+
+    #[test]
+    fn skip_leaves_out_duplicates_of_stored_and_earlier_reviews() {
+        let reviews = [review("new", "P1"), review("stored", "P1"), review("new", "P1"), review("new", "P2")];
+        let plan = plan(&reviews, DuplicateAction::Skip).unwrap();
+        assert_eq!(plan.write, [0, 3]);
+        let ids = [Some(20), None, None, Some(21)];
+        assert_eq!(matched(&plan.duplicates(&ids)), [(1, 10), (2, 20)]);
+    }
+
+    #[test]
+    fn flag_writes_everything_and_points_at_the_first_copy() {
+        let reviews = [review("new", "P1"), review("stored", "P1"), review("new", "P1"), review("new", "P1")];
+        let plan = plan(&reviews, DuplicateAction::Flag).unwrap();
+        assert_eq!(plan.write, [0, 1, 2, 3]);
+        let ids = [Some(20), Some(21), Some(22), Some(23)];
+        assert_eq!(matched(&plan.duplicates(&ids)), [(1, 10), (2, 20), (3, 20)]);
+    }
+
+    #[test]
+    fn near_duplicates_within_a_bulk_use_the_closest_earlier_review() {
+        let reviews = [review("a", "P1"), review("b", "P1"), review("c", "P1")];
+        let embeddings = vec![vec![0.0, 0.0], vec![3.0, 0.0], vec![2.5, 0.0]];
+        let plan = plan_bulk(&reviews, &embeddings, DuplicateAction::Flag, Some(1.0), find_stored).unwrap();
+        let duplicates = plan.duplicates(&[Some(1), Some(2), Some(3)]);
+        assert_eq!(matched(&duplicates), [(2, 2)]);
+        assert_eq!(duplicates[0].1.kind, DuplicateKind::Near);
+        assert_eq!(duplicates[0].1.score, 1.0 / 1.25);
+    }
+}
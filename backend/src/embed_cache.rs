@@ -0,0 +1,172 @@
+//! cache ของ embedding แบบ LRU ใน memory
+//!
+//! key คือข้อความหลัง [`crate::text::preprocess`] ข้อความที่ต่างกันแค่รูปอักษร (NFKC, zero-width) จึงใช้ entry เดียวกัน
+//! เมื่อเต็มจะทิ้ง entry ที่ถูกใช้ล่าสุดนานที่สุด ขนาด 0 คือปิด cache (ยังนับ miss)
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+pub struct EmbeddingCache {
+    capacity: usize,
+    entries: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct Lru {
+    /// ข้อความ → (ครั้งที่ใช้ล่าสุด, embedding)
+    map: HashMap<String, (u64, Vec<f32>)>,
+    /// ครั้งที่ใช้ล่าสุด → ข้อความ เรียงจากเก่าไปใหม่
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+/// ตัวเลขที่ `GET /metrics` คืน
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl EmbeddingCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Lru::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// embedding ของ `text` ถ้ามีใน cache (นับเป็น hit หรือ miss)
+    pub fn get(&self, text: &str) -> Option<Vec<f32>> {
+        let found = self.entries.lock().unwrap().get(text);
+        let counter = if found.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    pub fn insert(&self, text: String, embedding: Vec<f32>) {
+        if self.capacity > 0 {
+            self.entries.lock().unwrap().insert(text, embedding, self.capacity);
+        }
+    }
+
+    /// ดึงจาก cache หรือ embed แล้วเก็บไว้ ไม่ถือ lock ระหว่าง embed
+    pub fn get_or_embed<E>(&self, text: &str, embed: impl FnOnce(&str) -> Result<Vec<f32>, E>) -> Result<Vec<f32>, E> {
+        if let Some(embedding) = self.get(text) {
+            return Ok(embedding);
+        }
+        let embedding = embed(text)?;
+        self.insert(text.to_string(), embedding.clone());
+        Ok(embedding)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.lock().unwrap().map.len(),
+            capacity: self.capacity,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Lru {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn get(&mut self, text: &str) -> Option<Vec<f32>> {
+        let tick = self.next_tick();
+        let (used, embedding) = self.map.get_mut(text)?;
+        let key = self.order.remove(used).expect("every cached text has an order entry");
+        *used = tick;
+        self.order.insert(tick, key);
+        Some(embedding.clone())
+    }
+
+    fn insert(&mut self, text: String, embedding: Vec<f32>, capacity: usize) {
+        let tick = self.next_tick();
+        if let Some((used, _)) = self.map.insert(text.clone(), (tick, embedding)) {
+            self.order.remove(&used);
+        }
+        self.order.insert(tick, text);
+        while self.map.len() > capacity {
+            let (_, oldest) = self.order.pop_first().expect("order has an entry for every cached text");
+            self.map.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embed(text: &str) -> Result<Vec<f32>, ()> {
+        Ok(vec![text.len() as f32])
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entry() {
+        let cache = EmbeddingCache::new(2);
+        cache.insert("a".to_string(), vec![1.0]);
+        cache.insert("b".to_string(), vec![2.0]);
+        // ใช้ "a" ล่าสุด "b" จึงเก่าที่สุด
+        assert_eq!(cache.get("a"), Some(vec![1.0]));
+        cache.insert("c".to_string(), vec![3.0]);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(vec![1.0]));
+        assert_eq!(cache.get("c"), Some(vec![3.0]));
+
+        // insert ซ้ำนับเป็นการใช้ ไม่เพิ่ม entry
+        cache.insert("a".to_string(), vec![4.0]);
+        cache.insert("d".to_string(), vec![5.0]);
+        assert_eq!(cache.get("c"), None);
+        assert_eq!(cache.get("a"), Some(vec![4.0]));
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[test]
+    fn capacity_zero_disables_the_cache() {
+        let cache = EmbeddingCache::new(0);
+        let mut calls = 0;
+        for _ in 0..3 {
+            let embedding = cache
+                .get_or_embed("text", |t| {
+                    calls += 1;
+                    embed(t)
+                })
+                .unwrap();
+            assert_eq!(embedding, vec![4.0]);
+        }
+        assert_eq!(calls, 3);
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.capacity, stats.hits, stats.misses), (0, 0, 0, 3));
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let cache = EmbeddingCache::new(4);
+        let mut calls = 0;
+        for text in ["one", "two", "one", "one", "three"] {
+            cache
+                .get_or_embed(text, |t| {
+                    calls += 1;
+                    embed(t)
+                })
+                .unwrap();
+        }
+        assert_eq!(calls, 3);
+        assert_eq!(cache.get_or_embed("four", |_| Err(())), Err(()));
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (3, 2, 4));
+    }
+}
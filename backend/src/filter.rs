@@ -0,0 +1,152 @@
+//! filter ของ `/search` และ `GET /reviews` ตาม metadata: `product_id` และช่วง `review_rating`
+//!
+//! attribute ของทุก review ถูกเก็บไว้ใน memory เพื่อให้ spfresh เช็ค filter ได้ระหว่างสแกน
+//! candidate โดยไม่ต้องอ่าน `reviews.jsonl` และเรียงตาม id เพื่อใช้แบ่งหน้าด้วย cursor
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
+
+use crate::Review;
+use crate::store::ReviewId;
+
+struct Attributes {
+    product_id: String,
+    rating: u8,
+}
+
+/// review id → attribute ที่ใช้ filter
+#[derive(Default)]
+pub struct AttributeIndex {
+    by_id: BTreeMap<ReviewId, Attributes>,
+    /// product_id → id ของ review สินค้านั้น
+    by_product: HashMap<String, BTreeSet<ReviewId>>,
+}
+
+impl AttributeIndex {
+    pub fn insert(&mut self, id: ReviewId, review: &Review) {
+        self.remove(id);
+        self.by_product.entry(review.product_id.clone()).or_default().insert(id);
+        self.by_id.insert(
+            id,
+            Attributes {
+                product_id: review.product_id.clone(),
+                rating: review.review_rating,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, id: ReviewId) {
+        let Some(old) = self.by_id.remove(&id) else {
+            return;
+        };
+        if let Some(ids) = self.by_product.get_mut(&old.product_id) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.by_product.remove(&old.product_id);
+            }
+        }
+    }
+
+    /// id ที่ผ่าน `filter` เรียงจากน้อยไปมาก เริ่มหลัง `after` (ไม่รวม `after`)
+    /// ถ้าระบุ `product_id` จะไล่เฉพาะ review ของสินค้านั้น
+    pub fn ids_after<'a>(&'a self, after: Option<ReviewId>, filter: &'a ReviewFilter) -> Box<dyn Iterator<Item = ReviewId> + 'a> {
+        let range = (after.map_or(Bound::Unbounded, Bound::Excluded), Bound::Unbounded);
+        let ids: Box<dyn Iterator<Item = ReviewId> + 'a> = match &filter.product_id {
+            Some(p) => match self.by_product.get(p) {
+                Some(ids) => Box::new(ids.range(range).copied()),
+                None => Box::new(std::iter::empty()),
+            },
+            None => Box::new(self.by_id.range(range).map(|(&id, _)| id)),
+        };
+        Box::new(ids.filter(move |&id| filter.matches(self, id)))
+    }
+}
+
+/// เงื่อนไขของ `/search` และ `GET /reviews` ทุก field เป็น optional และต้องผ่านทุกข้อ
+#[derive(Debug, Default)]
+pub struct ReviewFilter {
+    pub product_id: Option<String>,
+    pub min_rating: Option<u8>,
+    pub max_rating: Option<u8>,
+}
+
+impl ReviewFilter {
+    pub fn is_empty(&self) -> bool {
+        self.product_id.is_none() && self.min_rating.is_none() && self.max_rating.is_none()
+    }
+
+    /// review `id` ผ่าน filter หรือไม่ (id ที่ไม่รู้จักถือว่าไม่ผ่าน)
+    pub fn matches(&self, attributes: &AttributeIndex, id: ReviewId) -> bool {
+        let Some(a) = attributes.by_id.get(&id) else {
+            return false;
+        };
+        self.product_id.as_ref().is_none_or(|p| *p == a.product_id)
+            && self.min_rating.is_none_or(|r| a.rating >= r)
+            && self.max_rating.is_none_or(|r| a.rating <= r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(product_id: &str, rating: u8) -> Review {
+        Review {
+            review_title: String::new(),
+            review_body: String::new(),
+            product_id: product_id.to_string(),
+            review_rating: rating,
+        }
+    }
+
+    /// id 1..=6: สินค้า A คะแนน 1..3 และสินค้า B คะแนน 3..5
+    fn attributes() -> AttributeIndex {
+        let mut index = AttributeIndex::default();
+        for (id, product, rating) in [(1, "A", 1), (2, "B", 3), (3, "A", 2), (4, "B", 4), (5, "A", 3), (6, "B", 5)] {
+            index.insert(id, &review(product, rating));
+        }
+        index
+    }
+
+    fn filter(product_id: Option<&str>, min_rating: Option<u8>, max_rating: Option<u8>) -> ReviewFilter {
+        ReviewFilter { product_id: product_id.map(str::to_string), min_rating, max_rating }
+    }
+
+    #[test]
+    fn matches_requires_every_condition() {
+        let index = attributes();
+        let f = filter(Some("B"), Some(4), None);
+        assert!(f.matches(&index, 4) && f.matches(&index, 6));
+        assert!(!f.matches(&index, 2) && !f.matches(&index, 5));
+        assert!(filter(None, Some(2), Some(3)).matches(&index, 3));
+        assert!(!filter(None, Some(2), Some(3)).matches(&index, 1));
+        // id ที่ไม่รู้จักไม่ผ่านแม้ filter ว่าง
+        assert!(!ReviewFilter::default().matches(&index, 99));
+    }
+
+    #[test]
+    fn ids_after_pages_in_id_order() {
+        let index = attributes();
+        let all = ReviewFilter::default();
+        assert_eq!(index.ids_after(None, &all).collect::<Vec<_>>(), [1, 2, 3, 4, 5, 6]);
+        assert_eq!(index.ids_after(Some(4), &all).collect::<Vec<_>>(), [5, 6]);
+        let a = filter(Some("A"), Some(2), None);
+        assert_eq!(index.ids_after(None, &a).collect::<Vec<_>>(), [3, 5]);
+        assert_eq!(index.ids_after(Some(3), &a).collect::<Vec<_>>(), [5]);
+        assert_eq!(index.ids_after(None, &filter(Some("C"), None, None)).count(), 0);
+    }
+
+    #[test]
+    fn insert_replaces_and_remove_forgets_attributes() {
+        let mut index = attributes();
+        // แก้ review ให้เป็นสินค้าอื่น: ต้องไม่อยู่ใน list ของสินค้าเดิม
+        index.insert(1, &review("B", 5));
+        assert_eq!(index.ids_after(None, &filter(Some("A"), None, None)).collect::<Vec<_>>(), [3, 5]);
+        assert!(filter(Some("B"), Some(5), None).matches(&index, 1));
+
+        index.remove(3);
+        index.remove(5);
+        assert!(!index.by_product.contains_key("A"));
+        assert!(!ReviewFilter::default().matches(&index, 3));
+    }
+}
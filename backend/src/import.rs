@@ -0,0 +1,377 @@
+//! แปลงไฟล์ CSV หรือ JSONL ที่อัปโหลดเป็น review ทีละแถว
+//!
+//! ข้อมูลเข้ามาเป็น chunk ตามที่อ่านได้จาก request จึงไม่ต้องเก็บทั้งไฟล์ไว้ใน memory
+//! แถวที่ไม่ผ่านการตรวจจะถูกรายงานพร้อมเลขแถว ส่วนแถวอื่นนำเข้าต่อได้ตามปกติ
+//!
+//! ชื่อ column ของแต่ละ field ตั้งได้ด้วย [`ColumnMapping`] เช่น dataset รีวิวบน Kaggle
+//! (Amazon Fine Food Reviews) ใช้ `{"review_title": "Summary", "review_body": "Text",
+//! "product_id": "ProductId", "review_rating": "Score"}`
+
+use csv_core::{ReadRecordResult, Reader};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::Review;
+
+/// ช่วงของ `review_rating` ที่ยอมรับตอนนำเข้า
+const MIN_RATING: u8 = 1;
+const MAX_RATING: u8 = 5;
+
+/// UTF-8 BOM ที่ Excel ใส่ไว้หน้าไฟล์
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Csv,
+    Jsonl,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "jsonl" | "ndjson" => Some(Self::Jsonl),
+            _ => None,
+        }
+    }
+
+    /// เดาจากนามสกุลของไฟล์
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        file_name.rsplit_once('.').and_then(|(_, ext)| Self::from_name(ext))
+    }
+}
+
+/// ชื่อ column (CSV) หรือ key (JSONL) ของแต่ละ field ค่าเริ่มต้นคือชื่อ field เอง
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ColumnMapping {
+    pub review_title: String,
+    pub review_body: String,
+    pub product_id: String,
+    pub review_rating: String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        Self {
+            review_title: "review_title".to_string(),
+            review_body: "review_body".to_string(),
+            product_id: "product_id".to_string(),
+            review_rating: "review_rating".to_string(),
+        }
+    }
+}
+
+/// แถวที่นำเข้าไม่ได้ `row` นับแถวข้อมูลเริ่มที่ 1 (ไม่นับ header ของ CSV และบรรทัดว่าง)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RowError {
+    pub row: u64,
+    pub error: String,
+}
+
+/// แถวที่อ่านได้: เลขแถว กับ review หรือเหตุผลที่ใช้ไม่ได้
+pub type Row = (u64, Result<Review, String>);
+
+/// อ่าน review จาก chunk ที่ทยอยเข้ามา
+pub struct RowReader {
+    mapping: ColumnMapping,
+    row: u64,
+    format: FormatState,
+}
+
+enum FormatState {
+    Csv(Box<CsvState>),
+    Jsonl { pending: Vec<u8> },
+}
+
+struct CsvState {
+    reader: Reader,
+    record: Vec<u8>,
+    record_len: usize,
+    ends: Vec<usize>,
+    ends_len: usize,
+    /// ตำแหน่ง column ของ title, body, product_id, rating (ได้หลังอ่าน header)
+    columns: Option<[usize; 4]>,
+}
+
+impl RowReader {
+    pub fn new(format: Format, mapping: ColumnMapping) -> Self {
+        let format = match format {
+            Format::Csv => FormatState::Csv(Box::new(CsvState {
+                reader: Reader::new(),
+                record: vec![0; 4096],
+                record_len: 0,
+                ends: vec![0; 16],
+                ends_len: 0,
+                columns: None,
+            })),
+            Format::Jsonl => FormatState::Jsonl { pending: Vec::new() },
+        };
+        Self { mapping, row: 0, format }
+    }
+
+    /// อ่านแถวที่ครบแล้วใน `chunk` ต่อท้าย `rows` คืน error ถ้าทั้งไฟล์ใช้ไม่ได้ (เช่น header ไม่มี column ที่ต้องใช้)
+    pub fn feed(&mut self, chunk: &[u8], rows: &mut Vec<Row>) -> Result<(), String> {
+        // csv-core ถือว่า input ว่างคือจบไฟล์
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        self.read(chunk, rows)
+    }
+
+    /// อ่านแถวสุดท้ายที่ไม่มีขึ้นบรรทัดใหม่ปิดท้าย
+    pub fn finish(&mut self, rows: &mut Vec<Row>) -> Result<(), String> {
+        self.read(&[], rows)?;
+        if let FormatState::Csv(csv) = &self.format
+            && csv.columns.is_none()
+        {
+            return Err("CSV file has no header row".to_string());
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, mut input: &[u8], rows: &mut Vec<Row>) -> Result<(), String> {
+        match &mut self.format {
+            FormatState::Jsonl { pending } => {
+                let eof = input.is_empty();
+                pending.extend_from_slice(input);
+                let mut start = 0;
+                while let Some(n) = pending[start..].iter().position(|&b| b == b'\n') {
+                    push_json_row(&self.mapping, &mut self.row, &pending[start..start + n], rows);
+                    start += n + 1;
+                }
+                pending.drain(..start);
+                if eof {
+                    push_json_row(&self.mapping, &mut self.row, pending, rows);
+                    pending.clear();
+                }
+            }
+            FormatState::Csv(csv) => loop {
+                let (result, nin, nout, nend) = csv.reader.read_record(
+                    input,
+                    &mut csv.record[csv.record_len..],
+                    &mut csv.ends[csv.ends_len..],
+                );
+                input = &input[nin..];
+                csv.record_len += nout;
+                csv.ends_len += nend;
+                match result {
+                    ReadRecordResult::InputEmpty | ReadRecordResult::End => break,
+                    ReadRecordResult::OutputFull => csv.record.resize(csv.record.len() * 2, 0),
+                    ReadRecordResult::OutputEndsFull => csv.ends.resize(csv.ends.len() * 2, 0),
+                    ReadRecordResult::Record => {
+                        let fields: Vec<&[u8]> = (0..csv.ends_len)
+                            .map(|i| {
+                                let start = if i == 0 { 0 } else { csv.ends[i - 1] };
+                                &csv.record[start..csv.ends[i]]
+                            })
+                            .collect();
+                        match csv.columns {
+                            None => csv.columns = Some(header_columns(&self.mapping, &fields)?),
+                            // บรรทัดว่างได้ field ว่าง field เดียว
+                            Some(_) if fields.len() == 1 && fields[0].is_empty() => {}
+                            Some(columns) => {
+                                self.row += 1;
+                                rows.push((self.row, csv_review(&self.mapping, columns, &fields)));
+                            }
+                        }
+                        csv.record_len = 0;
+                        csv.ends_len = 0;
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+fn header_columns(mapping: &ColumnMapping, header: &[&[u8]]) -> Result<[usize; 4], String> {
+    let find = |name: &str| {
+        header
+            .iter()
+            .position(|h| String::from_utf8_lossy(h.strip_prefix(BOM).unwrap_or(h)).trim() == name)
+            .ok_or_else(|| format!("CSV header has no column {:?}", name))
+    };
+    Ok([
+        find(&mapping.review_title)?,
+        find(&mapping.review_body)?,
+        find(&mapping.product_id)?,
+        find(&mapping.review_rating)?,
+    ])
+}
+
+fn csv_review(mapping: &ColumnMapping, columns: [usize; 4], fields: &[&[u8]]) -> Result<Review, String> {
+    let names = [&mapping.review_title, &mapping.review_body, &mapping.product_id, &mapping.review_rating];
+    let mut values = Vec::with_capacity(columns.len());
+    for (column, name) in columns.into_iter().zip(names) {
+        let field = fields.get(column).ok_or_else(|| format!("row has no {:?} column", name))?;
+        let value = std::str::from_utf8(field).map_err(|_| format!("column {:?} is not valid UTF-8", name))?;
+        values.push(value.to_string());
+    }
+    let [title, body, product_id, rating] = <[String; 4]>::try_from(values).unwrap();
+    validate(title, body, product_id, &rating)
+}
+
+fn push_json_row(mapping: &ColumnMapping, row: &mut u64, line: &[u8], rows: &mut Vec<Row>) {
+    let line = if *row == 0 { line.strip_prefix(BOM).unwrap_or(line) } else { line };
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return;
+    }
+    *row += 1;
+    rows.push((*row, json_review(mapping, line)));
+}
+
+fn json_review(mapping: &ColumnMapping, line: &[u8]) -> Result<Review, String> {
+    let object: Map<String, Value> = serde_json::from_slice(line).map_err(|e| format!("not a JSON object: {}", e))?;
+    let text = |name: &str| match object.get(name) {
+        None | Some(Value::Null) => Ok(String::new()),
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(Value::Number(n)) => Ok(n.to_string()),
+        Some(_) => Err(format!("{:?} must be a string or a number", name)),
+    };
+    validate(
+        text(&mapping.review_title)?,
+        text(&mapping.review_body)?,
+        text(&mapping.product_id)?,
+        &text(&mapping.review_rating)?,
+    )
+}
+
+fn validate(review_title: String, review_body: String, product_id: String, rating: &str) -> Result<Review, String> {
+    let product_id = product_id.trim().to_string();
+    if product_id.is_empty() {
+        return Err("product_id is empty".to_string());
+    }
+    if review_title.trim().is_empty() && review_body.trim().is_empty() {
+        return Err("review_title and review_body are both empty".to_string());
+    }
+    // บาง dataset เก็บคะแนนเป็นทศนิยม เช่น "4.0"
+    let review_rating = match rating.trim().parse::<f32>() {
+        Ok(r) if r.fract() == 0.0 && (MIN_RATING as f32..=MAX_RATING as f32).contains(&r) => r as u8,
+        _ => {
+            return Err(format!(
+                "review_rating {:?} is not a whole number from {} to {}",
+                rating, MIN_RATING, MAX_RATING
+            ));
+        }
+    };
+    Ok(Review {
+        review_title,
+        review_body,
+        product_id,
+        review_rating,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// อ่านทั้ง `input` โดยส่งเข้าไปทีละ `chunk` byte
+    fn read(format: Format, mapping: ColumnMapping, input: &[u8], chunk: usize) -> Result<Vec<Row>, String> {
+        let mut reader = RowReader::new(format, mapping);
+        let mut rows = Vec::new();
+        for part in input.chunks(chunk) {
+            reader.feed(part, &mut rows)?;
+        }
+        reader.finish(&mut rows)?;
+        Ok(rows)
+    }
+
+    fn read_all(format: Format, input: &str) -> Vec<Row> {
+        read(format, ColumnMapping::default(), input.as_bytes(), input.len().max(1)).unwrap()
+    }
+
+    fn review(title: &str, body: &str, product_id: &str, rating: u8) -> Review {
+        Review {
+            review_title: title.to_string(),
+            review_body: body.to_string(),
+            product_id: product_id.to_string(),
+            review_rating: rating,
+        }
+    }
+
+    const CSV: &str = "review_title,review_body,product_id,review_rating\n\
+        \"Great, really\",\"Line one\nline \"\"two\"\"\",P1,5\n\
+        \n\
+        Bad,rating,P2,9\n\
+        Last,no newline,P3,4.0";
+
+    #[test]
+    fn csv_handles_quoted_commas_newlines_and_escaped_quotes() {
+        let rows = read_all(Format::Csv, CSV);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], (1, Ok(review("Great, really", "Line one\nline \"two\"", "P1", 5))));
+        // บรรทัดว่างไม่นับเป็นแถว
+        assert_eq!(rows[1].0, 2);
+        assert!(rows[1].1.as_ref().unwrap_err().contains("review_rating"));
+        assert_eq!(rows[2], (3, Ok(review("Last", "no newline", "P3", 4))));
+    }
+
+    #[test]
+    fn records_split_across_chunks_read_the_same() {
+        let whole = read_all(Format::Csv, CSV);
+        for chunk in [1, 2, 3, 7, 16] {
+            assert_eq!(read(Format::Csv, ColumnMapping::default(), CSV.as_bytes(), chunk).unwrap(), whole, "chunk {}", chunk);
+        }
+
+        let jsonl = "{\"review_title\":\"ดีมาก\",\"product_id\":\"P1\",\"review_rating\":5}\n{\"review_body\":\"x\",\"product_id\":2,\"review_rating\":\"3\"}";
+        let whole = read_all(Format::Jsonl, jsonl);
+        assert_eq!(whole.len(), 2);
+        assert_eq!(whole[1], (2, Ok(review("", "x", "2", 3))));
+        for chunk in [1, 2, 5] {
+            assert_eq!(read(Format::Jsonl, ColumnMapping::default(), jsonl.as_bytes(), chunk).unwrap(), whole, "chunk {}", chunk);
+        }
+    }
+
+    #[test]
+    fn bom_and_crlf_are_accepted() {
+        let csv = "\u{FEFF}review_title,review_body,product_id,review_rating\r\nA,b,P1,5\r\n\r\nC,d,P2,4\r\n";
+        // BOM ถูกแบ่งไปคนละ chunk ก็ยังอ่านได้
+        for chunk in [1, csv.len()] {
+            let rows = read(Format::Csv, ColumnMapping::default(), csv.as_bytes(), chunk).unwrap();
+            assert_eq!(rows, vec![(1, Ok(review("A", "b", "P1", 5))), (2, Ok(review("C", "d", "P2", 4)))]);
+        }
+
+        let jsonl = "\u{FEFF}{\"review_title\":\"A\",\"product_id\":\"P1\",\"review_rating\":5}\r\n\r\n{\"review_title\":\"C\",\"product_id\":\"P2\",\"review_rating\":4}\r\n";
+        let rows = read_all(Format::Jsonl, jsonl);
+        assert_eq!(rows, vec![(1, Ok(review("A", "", "P1", 5))), (2, Ok(review("C", "", "P2", 4)))]);
+    }
+
+    #[test]
+    fn missing_mapped_column_fails_the_file() {
+        let mapping = ColumnMapping { review_rating: "Score".to_string(), ..ColumnMapping::default() };
+        let csv = b"review_title,review_body,product_id,review_rating\nA,b,P1,5\n";
+        let err = read(Format::Csv, mapping, csv, csv.len()).unwrap_err();
+        assert!(err.contains("\"Score\""), "{}", err);
+
+        let err = read(Format::Csv, ColumnMapping::default(), b"", 1).unwrap_err();
+        assert!(err.contains("no header"), "{}", err);
+    }
+
+    #[test]
+    fn short_rows_and_bad_json_fail_only_their_row() {
+        let rows = read_all(Format::Csv, "review_title,review_body,product_id,review_rating\nA,b\nC,d,P2,4\n");
+        assert!(rows[0].1.as_ref().unwrap_err().contains("no \"product_id\" column"));
+        assert_eq!(rows[1], (2, Ok(review("C", "d", "P2", 4))));
+
+        let rows = read_all(Format::Jsonl, "not json\n[1]\n{\"review_title\":\"A\",\"product_id\":\"P\",\"review_rating\":1}\n");
+        assert_eq!(rows.len(), 3);
+        assert!(rows[0].1.is_err() && rows[1].1.is_err());
+        assert_eq!(rows[2], (3, Ok(review("A", "", "P", 1))));
+    }
+
+    #[test]
+    fn mapping_renames_columns() {
+        let mapping = ColumnMapping {
+            review_title: "Summary".to_string(),
+            review_body: "Text".to_string(),
+            product_id: "ProductId".to_string(),
+            review_rating: "Score".to_string(),
+        };
+        let csv = b"Id,ProductId,Score,Summary,Text\n1,B001,5,Good,Tasty\n";
+        let rows = read(Format::Csv, mapping, csv, 4).unwrap();
+        assert_eq!(rows, vec![(1, Ok(review("Good", "Tasty", "B001", 5)))]);
+    }
+}
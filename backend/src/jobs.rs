@@ -0,0 +1,362 @@
+//! งานนำเข้า review แบบ background (`data/jobs/`)
+//!
+//! ไฟล์ที่อัปโหลดถูกเก็บเป็น `<id>.input` และสถานะของงานเป็น `<id>.json` ซึ่งถูกเขียนใหม่ทั้งไฟล์
+//! (เขียน `.tmp` แล้ว rename) ทุกครั้งที่นำเข้าครบหนึ่งชุด งานที่ยังไม่จบตอน process ตาย
+//! จะถูกทำต่อจากแถวสุดท้ายที่บันทึกไว้เมื่อ start ใหม่ ไฟล์ input ถูกลบเมื่องานจบ
+//! ยกเว้นงานที่ล้มเหลว ซึ่งจะถูกทำต่อเมื่อ start ใหม่เหมือนงานที่ยังไม่จบ
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::import::{ColumnMapping, Format, RowError};
+use crate::store::ReviewId;
+
+pub type JobId = u64;
+
+/// จำนวนแถวที่ผิดที่เก็บไว้เป็นตัวอย่างในสถานะของงาน
+const MAX_ERROR_SAMPLES: usize = 100;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Cancelled | Self::Failed)
+    }
+
+    /// งานที่ทำต่อเมื่อ start ใหม่ได้ (ยังไม่จบ หรือล้มเหลว)
+    fn is_resumable(self) -> bool {
+        self == Self::Failed || !self.is_finished()
+    }
+}
+
+/// สถานะของงาน (ตรงกับที่เก็บใน `<id>.json` และที่ `GET /jobs/{id}` คืน)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Job {
+    pub id: JobId,
+    pub status: JobStatus,
+    pub file_name: Option<String>,
+    pub format: Format,
+    pub mapping: ColumnMapping,
+    /// จำนวนแถวข้อมูลที่อ่านแล้ว (ทั้งที่นำเข้าได้และไม่ได้)
+    pub processed: u64,
+    pub imported: u64,
+    pub failed: u64,
+    /// ตัวอย่างแถวที่นำเข้าไม่ได้ (สูงสุด `MAX_ERROR_SAMPLES` แถวแรก)
+    pub errors: Vec<RowError>,
+    /// เหตุผลที่งานล้มเหลวทั้งงาน
+    pub error: Option<String>,
+    /// ชุดที่กำลังเขียนลง store (บันทึกก่อน commit)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending: Option<PendingBatch>,
+    /// unix time (วินาที)
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// ชุดของงานที่บันทึกไว้ก่อนเขียนลง store ถ้า process ตายก่อนบันทึกผล ตอน start ใหม่ใช้ `ids`
+/// บอกว่าชุดนี้ถูก commit แล้วหรือยัง (ทั้งชุดเขียนใน commit เดียว จึงถูกเขียนทั้งชุดหรือไม่ถูกเลย)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingBatch {
+    /// id ที่ review ในชุดได้ตามลำดับแถว
+    pub ids: Range<ReviewId>,
+    /// แถวสุดท้ายของชุด
+    pub last_row: u64,
+    /// แถวในชุดที่นำเข้าไม่ได้
+    pub failed: Vec<RowError>,
+}
+
+impl Job {
+    pub fn fail(&mut self, row: u64, error: String) {
+        self.failed += 1;
+        if self.errors.len() < MAX_ERROR_SAMPLES {
+            self.errors.push(RowError { row, error });
+        }
+    }
+
+    /// นับผลของชุดที่เขียนเสร็จแล้ว
+    pub fn complete(&mut self, batch: PendingBatch) {
+        self.processed = self.processed.max(batch.last_row);
+        self.imported += batch.ids.end - batch.ids.start;
+        for RowError { row, error } in batch.failed {
+            self.fail(row, error);
+        }
+    }
+}
+
+pub struct JobHandle {
+    job: Mutex<Job>,
+    cancelled: AtomicBool,
+    state_path: PathBuf,
+    input_path: PathBuf,
+}
+
+impl JobHandle {
+    pub fn snapshot(&self) -> Job {
+        self.job.lock().unwrap().clone()
+    }
+
+    /// แก้สถานะใน memory (ยังไม่บันทึกลงไฟล์)
+    pub fn update(&self, f: impl FnOnce(&mut Job)) {
+        let mut job = self.job.lock().unwrap();
+        f(&mut job);
+        job.updated_at = now();
+    }
+
+    /// บันทึกสถานะปัจจุบันลง `<id>.json` (ถือ lock จนเขียนเสร็จ ไม่ให้สองที่เขียน `.tmp` พร้อมกัน)
+    pub fn save(&self) -> io::Result<()> {
+        let job = self.job.lock().unwrap();
+        let bytes = serde_json::to_vec(&*job)?;
+        let tmp = self.state_path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.state_path)
+    }
+
+    pub fn input_path(&self) -> &PathBuf {
+        &self.input_path
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// ตั้งสถานะเป็น `running` ก่อนเริ่มทำงาน คืน `false` ถ้างานถูกยกเลิกไปก่อน
+    /// ตรวจและตั้งสถานะใน lock เดียวกับ [`cancel`](Self::cancel) งานที่เริ่มแล้วจึงไม่ถูกตั้งเป็น `cancelled` ทับ
+    pub fn start(&self) -> bool {
+        let mut job = self.job.lock().unwrap();
+        if self.is_cancelled() {
+            return false;
+        }
+        job.status = JobStatus::Running;
+        job.error = None;
+        job.updated_at = now();
+        true
+    }
+
+    /// ขอยกเลิกงาน งานที่ยังรอคิวจะเป็น `cancelled` ทันที งานที่กำลังทำจะหยุดก่อนเริ่มชุดถัดไป
+    /// review ที่นำเข้าไปแล้วยังอยู่ คืน `false` ถ้างานจบไปแล้ว
+    /// ไฟล์ input ถูกลบโดย worker ตอนรับรู้การยกเลิก (ดู [`start`](Self::start))
+    pub fn cancel(&self) -> io::Result<bool> {
+        {
+            let mut job = self.job.lock().unwrap();
+            if job.status.is_finished() {
+                return Ok(false);
+            }
+            self.cancelled.store(true, Ordering::Relaxed);
+            if job.status != JobStatus::Queued {
+                return Ok(true);
+            }
+            job.status = JobStatus::Cancelled;
+            job.updated_at = now();
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// ตั้งสถานะสุดท้าย บันทึก แล้วลบไฟล์ input (งานที่ล้มเหลวเก็บไว้ทำต่อ)
+    pub fn finish(&self, status: JobStatus) -> io::Result<()> {
+        self.update(|job| job.status = status);
+        self.save()?;
+        if status == JobStatus::Failed {
+            return Ok(());
+        }
+        match fs::remove_file(&self.input_path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct Jobs {
+    dir: PathBuf,
+    jobs: Mutex<BTreeMap<JobId, Arc<JobHandle>>>,
+    next_id: AtomicU64,
+}
+
+impl Jobs {
+    /// โหลดสถานะของทุกงานใน `dir` (สร้างโฟลเดอร์ถ้ายังไม่มี)
+    /// ไฟล์ `.tmp` ที่เขียนค้าง input ของงานที่ยังไม่ได้บันทึกสถานะ และ input ของงานที่จบแล้ว
+    /// (เช่นงานที่ถูกยกเลิกก่อน worker จะรับรู้) จะถูกลบ
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut jobs = BTreeMap::new();
+        let mut inputs = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("json") => {
+                    let job: Job = serde_json::from_slice(&fs::read(&path)?).map_err(|e| {
+                        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+                    })?;
+                    let handle = Self::handle(&dir, job);
+                    jobs.insert(handle.snapshot().id, Arc::new(handle));
+                }
+                Some("tmp") => fs::remove_file(&path)?,
+                Some("input") => inputs.push(path),
+                _ => {}
+            }
+        }
+        for path in inputs {
+            let resumable = path
+                .file_stem()
+                .and_then(|s| s.to_str()?.parse::<JobId>().ok())
+                .and_then(|id| jobs.get(&id))
+                .is_some_and(|job| job.snapshot().status.is_resumable());
+            if !resumable {
+                fs::remove_file(&path)?;
+            }
+        }
+
+        let next_id = jobs.keys().next_back().map_or(1, |id| id + 1);
+        Ok(Self {
+            dir,
+            jobs: Mutex::new(jobs),
+            next_id: AtomicU64::new(next_id),
+        })
+    }
+
+    fn handle(dir: &std::path::Path, job: Job) -> JobHandle {
+        JobHandle {
+            cancelled: AtomicBool::new(job.status == JobStatus::Cancelled),
+            state_path: dir.join(format!("{}.json", job.id)),
+            input_path: dir.join(format!("{}.input", job.id)),
+            job: Mutex::new(job),
+        }
+    }
+
+    /// จอง id ของงานใหม่ คืน id กับ path ที่ต้องเขียนไฟล์ input
+    pub fn reserve(&self) -> (JobId, PathBuf) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        (id, self.dir.join(format!("{}.input", id)))
+    }
+
+    /// สร้างงานที่เขียน input ไว้แล้ว สถานะเริ่มต้นเป็น `queued`
+    pub fn create(
+        &self,
+        id: JobId,
+        file_name: Option<String>,
+        format: Format,
+        mapping: ColumnMapping,
+    ) -> io::Result<Arc<JobHandle>> {
+        let created_at = now();
+        let job = Job {
+            id,
+            status: JobStatus::Queued,
+            file_name,
+            format,
+            mapping,
+            processed: 0,
+            imported: 0,
+            failed: 0,
+            errors: Vec::new(),
+            error: None,
+            pending: None,
+            created_at,
+            updated_at: created_at,
+        };
+        let handle = Arc::new(Self::handle(&self.dir, job));
+        handle.save()?;
+        self.jobs.lock().unwrap().insert(id, handle.clone());
+        Ok(handle)
+    }
+
+    pub fn get(&self, id: JobId) -> Option<Arc<JobHandle>> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    /// งานที่ยังไม่จบ และงานที่ล้มเหลวซึ่งยังมีไฟล์ input เรียงตาม id
+    pub fn unfinished(&self) -> Vec<Arc<JobHandle>> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| job.snapshot().status.is_resumable() && job.input_path.exists())
+            .cloned()
+            .collect()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("backend-jobs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn queued(jobs: &Jobs) -> Arc<JobHandle> {
+        let (id, input) = jobs.reserve();
+        fs::write(&input, "review_title,review_body\n").unwrap();
+        jobs.create(id, None, Format::Csv, ColumnMapping::default()).unwrap()
+    }
+
+    #[test]
+    fn cancel_before_start_keeps_the_input_until_the_worker_finishes() {
+        let dir = temp_dir("cancel-queued");
+        let jobs = Jobs::open(&dir).unwrap();
+        let job = queued(&jobs);
+
+        assert!(job.cancel().unwrap());
+        assert_eq!(job.snapshot().status, JobStatus::Cancelled);
+        assert!(job.input_path().exists());
+        assert!(!job.start());
+        job.finish(JobStatus::Cancelled).unwrap();
+        assert!(!job.input_path().exists());
+        assert!(!job.cancel().unwrap());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn cancel_after_start_leaves_the_job_running() {
+        let dir = temp_dir("cancel-running");
+        let jobs = Jobs::open(&dir).unwrap();
+        let job = queued(&jobs);
+
+        assert!(job.start());
+        assert!(job.cancel().unwrap());
+        assert!(job.is_cancelled());
+        assert_eq!(job.snapshot().status, JobStatus::Running);
+        assert!(job.input_path().exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn open_removes_the_input_of_a_job_cancelled_before_the_worker_saw_it() {
+        let dir = temp_dir("cancel-restart");
+        let jobs = Jobs::open(&dir).unwrap();
+        let job = queued(&jobs);
+        assert!(job.cancel().unwrap());
+        let input = job.input_path().clone();
+        drop(jobs);
+
+        let jobs = Jobs::open(&dir).unwrap();
+        assert!(!input.exists());
+        assert!(jobs.unfinished().is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,188 @@
+//! lexical search ด้วย BM25 บน `review_title` + `review_body`
+//!
+//! inverted index อยู่ใน memory สร้างจาก `reviews.jsonl` ตอน start และเพิ่มทุกครั้งที่ insert
+//! ใช้หาคำที่ต้องตรงตัว เช่นชื่อรุ่นหรือ SKU ที่ vector search มักพลาด
+//! ตัดคำด้วย [`text::tokenize`](crate::text::tokenize) ทั้งตอน index และตอนค้นหา
+
+use std::collections::{HashMap, HashSet};
+
+use crate::store::ReviewId;
+use crate::text::tokenize;
+
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+/// ค่าคงที่ k ของ reciprocal rank fusion: `weight / (k + rank)`
+const RRF_K: f32 = 60.0;
+
+#[derive(Default)]
+pub struct Bm25Index {
+    /// คำ → (review id, จำนวนครั้งที่พบใน review นั้น)
+    postings: HashMap<String, Vec<(ReviewId, u32)>>,
+    /// จำนวนคำของแต่ละ review
+    doc_len: HashMap<ReviewId, u32>,
+    total_len: u64,
+}
+
+impl Bm25Index {
+    pub fn len(&self) -> usize {
+        self.doc_len.len()
+    }
+
+    pub fn insert(&mut self, id: ReviewId, text: &str) {
+        let tokens = tokenize(text);
+        let mut tf: HashMap<String, u32> = HashMap::new();
+        for t in &tokens {
+            *tf.entry(t.clone()).or_default() += 1;
+        }
+        for (term, n) in tf {
+            self.postings.entry(term).or_default().push((id, n));
+        }
+        self.doc_len.insert(id, tokens.len() as u32);
+        self.total_len += tokens.len() as u64;
+    }
+
+    /// เอา review ออก `text` ต้องเป็นข้อความเดียวกับตอน [`insert`](Self::insert)
+    pub fn remove(&mut self, id: ReviewId, text: &str) {
+        let Some(len) = self.doc_len.remove(&id) else {
+            return;
+        };
+        self.total_len -= len as u64;
+        for term in tokenize(text).into_iter().collect::<HashSet<_>>() {
+            if let Some(postings) = self.postings.get_mut(&term) {
+                postings.retain(|&(x, _)| x != id);
+                if postings.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    /// `top_k` review ที่คะแนน BM25 สูงสุดที่ผ่าน `filter` เรียงจากมากไปน้อย (คะแนนเท่ากันเรียงตาม id)
+    pub fn search(&self, query: &str, top_k: usize, filter: impl Fn(ReviewId) -> bool) -> Vec<(ReviewId, f32)> {
+        if self.doc_len.is_empty() {
+            return Vec::new();
+        }
+        let n = self.doc_len.len() as f32;
+        let avg_len = self.total_len as f32 / n;
+
+        let mut scores: HashMap<ReviewId, f32> = HashMap::new();
+        let terms: HashSet<String> = tokenize(query).into_iter().collect();
+        for term in &terms {
+            let Some(postings) = self.postings.get(term) else {
+                continue;
+            };
+            let df = postings.len() as f32;
+            let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+            for &(id, tf) in postings {
+                if !filter(id) {
+                    continue;
+                }
+                let tf = tf as f32;
+                let len = self.doc_len[&id] as f32;
+                *scores.entry(id).or_default() += idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * len / avg_len));
+            }
+        }
+
+        let mut ranked: Vec<(ReviewId, f32)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.truncate(top_k);
+        ranked
+    }
+}
+
+/// รวมสองอันดับด้วย reciprocal rank fusion: `weight / (RRF_K + rank)` จากอันดับ semantic
+/// บวก `(1 - weight) / (RRF_K + rank)` จากอันดับ lexical (id ที่ไม่อยู่ในฝั่งไหนได้ 0 จากฝั่งนั้น)
+/// คืน `top_k` id ที่คะแนนรวมสูงสุด เรียงจากมากไปน้อย (คะแนนเท่ากันเรียงตาม id)
+pub fn fuse(semantic: &[ReviewId], lexical: &[ReviewId], weight: f32, top_k: usize) -> Vec<(ReviewId, f32)> {
+    let mut scores: HashMap<ReviewId, f32> = HashMap::new();
+    for (rank, &id) in semantic.iter().enumerate() {
+        *scores.entry(id).or_default() += weight / (RRF_K + (rank + 1) as f32);
+    }
+    for (rank, &id) in lexical.iter().enumerate() {
+        *scores.entry(id).or_default() += (1.0 - weight) / (RRF_K + (rank + 1) as f32);
+    }
+
+    let mut ranked: Vec<(ReviewId, f32)> = scores.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked.truncate(top_k);
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(docs: &[(ReviewId, &str)]) -> Bm25Index {
+        let mut index = Bm25Index::default();
+        for &(id, text) in docs {
+            index.insert(id, text);
+        }
+        index
+    }
+
+    fn ids(ranked: &[(ReviewId, f32)]) -> Vec<ReviewId> {
+        ranked.iter().map(|&(id, _)| id).collect()
+    }
+
+    #[test]
+    fn bm25_ranks_rarer_and_more_frequent_terms_higher() {
+        let index = index(&[
+            (1, "battery battery battery good"),
+            (2, "battery good"),
+            (3, "screen good"),
+            (4, "good good"),
+        ]);
+        // "battery" หายากกว่า "good" จึงนำ และ review 1 มีคำนี้หลายครั้งกว่า review 2
+        // review ที่มีแค่ "good" เรียงตามจำนวนครั้ง
+        assert_eq!(ids(&index.search("battery good", 10, |_| true)), [1, 2, 4, 3]);
+        assert_eq!(ids(&index.search("screen", 10, |_| true)), [3]);
+        assert!(index.search("camera", 10, |_| true).is_empty());
+    }
+
+    #[test]
+    fn bm25_prefers_shorter_documents_for_the_same_term_count() {
+        let index = index(&[(1, "battery lasts long on a single charge every day"), (2, "battery lasts")]);
+        let ranked = index.search("battery", 10, |_| true);
+        assert_eq!(ids(&ranked), [2, 1]);
+        assert!(ranked[0].1 > ranked[1].1);
+    }
+
+    #[test]
+    fn bm25_applies_the_filter_and_top_k() {
+        let index = index(&[(1, "sku x100"), (2, "sku x100 x100"), (3, "sku x100")]);
+        assert_eq!(ids(&index.search("x100", 10, |id| id != 2)), [1, 3]);
+        assert_eq!(ids(&index.search("x100", 1, |_| true)), [2]);
+    }
+
+    #[test]
+    fn removed_reviews_are_not_found() {
+        let mut index = index(&[(1, "battery"), (2, "battery screen")]);
+        index.remove(1, "battery");
+        assert_eq!(index.len(), 1);
+        assert_eq!(ids(&index.search("battery", 10, |_| true)), [2]);
+    }
+
+    #[test]
+    fn fuse_follows_the_semantic_ranking_at_weight_one() {
+        let fused = fuse(&[1, 2, 3], &[3, 4], 1.0, 10);
+        assert_eq!(ids(&fused[..3]), [1, 2, 3]);
+        // review ที่มีแค่ฝั่ง lexical ได้ 0
+        assert_eq!(fused[3], (4, 0.0));
+    }
+
+    #[test]
+    fn fuse_follows_the_lexical_ranking_at_weight_zero() {
+        let fused = fuse(&[1, 2, 3], &[3, 4], 0.0, 10);
+        assert_eq!(ids(&fused[..2]), [3, 4]);
+        assert!(fused[2..].iter().all(|&(_, score)| score == 0.0));
+    }
+
+    #[test]
+    fn fuse_rewards_reviews_found_by_both_at_equal_weight() {
+        let fused = fuse(&[1, 2, 3], &[3, 4, 1], 0.5, 10);
+        // 1 และ 3 อยู่ทั้งสองฝั่ง จึงนำ 2 และ 4 ที่อยู่ฝั่งเดียว; 2 กับ 4 อันดับเท่ากันจึงเรียงตาม id
+        assert_eq!(ids(&fused), [1, 3, 2, 4]);
+        assert_eq!(fused[0].1, 0.5 / (RRF_K + 1.0) + 0.5 / (RRF_K + 3.0));
+        assert_eq!(ids(&fuse(&[1, 2, 3], &[3, 4, 1], 0.5, 2)), [1, 3]);
+    }
+}
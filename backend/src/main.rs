@@ -1,29 +1,55 @@
 use axum::{
-    routing::post,
-    extract::Json,
-    response::IntoResponse,
+    routing::{get, post},
+    extract::{multipart::Field, DefaultBodyLimit, Json, Multipart, Path, Query},
+    response::{IntoResponse, Response},
     http::StatusCode,
     Json as AxumJson,
     Router,
 };
 use serde::{Deserialize, Serialize};
 use std::{
-    fs::{OpenOptions, File},
-    io::{BufRead, BufReader, Write},
+    collections::HashMap,
+    ops::Range,
+    io::Read,
     net::SocketAddr,
-    sync::Mutex,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
 };
 use once_cell::sync::Lazy;
+use tokio::io::AsyncWriteExt;
 
-use fastembed::embed;
-use spfresh::search;
+use fastembed::{TextEmbedding, DEFAULT_HASHING_DIM};
+use spfresh::{spawn_rebuilder, Index, IndexConfig, SearchParams};
+
+mod check;
+mod compact;
+mod dedupe;
+mod embed_cache;
+mod filter;
+mod import;
+mod jobs;
+mod lexical;
+mod offset_index;
+mod store;
+mod text;
+mod tombstone;
+mod vector_file;
+mod worker;
+use dedupe::{same_content, ContentIndex, DuplicateAction, DuplicateKind, DuplicateMatch};
+use embed_cache::{CacheStats, EmbeddingCache};
+use filter::{AttributeIndex, ReviewFilter};
+use import::{ColumnMapping, Format, Row, RowError, RowReader};
+use jobs::{JobHandle, JobId, JobStatus, Jobs, PendingBatch};
+use lexical::Bm25Index;
+use store::{ReviewId, ReviewStore};
+use tombstone::TombstoneLog;
 
 
 use tower_http::cors::{CorsLayer, Any};
 use tower::ServiceBuilder;
 
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 struct Review {
     review_title: String,
     review_body: String,
@@ -31,139 +57,1541 @@ struct Review {
     review_rating: u8,
 }
 
+/// review ที่เก็บใน metadata พร้อม id ที่ server แจก และ version (เริ่มที่ 1 เพิ่มทุกครั้งที่แก้)
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct StoredReview {
+    id: ReviewId,
+    #[serde(default = "first_version")]
+    version: u32,
+    #[serde(flatten)]
+    review: Review,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+impl StoredReview {
+    fn first(id: ReviewId, review: Review) -> Self {
+        Self {
+            id,
+            version: first_version(),
+            review,
+        }
+    }
+}
+
+/// `id` ไม่มีถ้า review ซ้ำแล้วถูกข้าม (`duplicates=skip`)
+#[derive(Serialize)]
+struct InsertResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<ReviewId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicate_of: Option<DuplicateMatch>,
+}
+
+/// query ของ `POST /reviews` และ `POST /reviews/bulk` สำหรับตรวจ review ซ้ำ (ดู [`dedupe`])
+#[derive(Deserialize)]
+struct DuplicateQuery {
+    /// `reject` / `skip` / `flag` ถ้าไม่ระบุจะไม่ตรวจ
+    duplicates: Option<DuplicateAction>,
+    /// ตรวจแบบ near ด้วย: review ที่ score ตั้งแต่ค่านี้ (0, 1] นับว่าซ้ำ
+    near_duplicate_score: Option<f32>,
+}
+
+#[derive(Clone, Copy)]
+struct DuplicateCheck {
+    action: DuplicateAction,
+    /// ระยะสูงสุดของ near-duplicate (`None` = ตรวจเฉพาะ exact)
+    max_distance: Option<f32>,
+}
+
+impl DuplicateQuery {
+    fn check(&self) -> Result<Option<DuplicateCheck>, (StatusCode, String)> {
+        let max_distance = match self.near_duplicate_score {
+            None => None,
+            Some(s) if s > 0.0 && s <= 1.0 => Some(1.0 / s - 1.0),
+            Some(_) => return Err((StatusCode::BAD_REQUEST, "near_duplicate_score must be in (0, 1]".to_string())),
+        };
+        match self.duplicates {
+            Some(action) => Ok(Some(DuplicateCheck { action, max_distance })),
+            None if max_distance.is_some() => {
+                Err((StatusCode::BAD_REQUEST, "near_duplicate_score requires duplicates".to_string()))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct UpdateResult {
+    id: ReviewId,
+    version: u32,
+}
+
+/// ทุก version ของ review เรียงจากเก่าไปใหม่
+#[derive(Serialize)]
+struct ReviewHistory {
+    id: ReviewId,
+    versions: Vec<StoredReview>,
+}
+
+/// query ของ `GET /reviews` ทุก field เป็น optional
+#[derive(Deserialize)]
+struct ListQuery {
+    /// `next_cursor` จากหน้าก่อนหน้า (เริ่มหลัง review id นี้)
+    cursor: Option<ReviewId>,
+    /// จำนวน review ต่อหน้า (ค่าเริ่มต้น `DEFAULT_PAGE_SIZE`)
+    limit: Option<usize>,
+    product_id: Option<String>,
+    /// เฉพาะ review ที่ได้คะแนนนี้พอดี (ใช้คู่กับ `min_rating`/`max_rating` ไม่ได้)
+    review_rating: Option<u8>,
+    /// เฉพาะ review ที่ `review_rating` อยู่ในช่วงนี้ (รวมขอบ)
+    min_rating: Option<u8>,
+    max_rating: Option<u8>,
+}
+
+/// review หนึ่งหน้าเรียงตาม id และ cursor ของหน้าถัดไป (`None` ถ้าเป็นหน้าสุดท้าย)
+#[derive(Serialize)]
+struct ReviewPage {
+    reviews: Vec<StoredReview>,
+    next_cursor: Option<ReviewId>,
+}
+
+/// `ids` ตามลำดับที่ส่งมา (`null` ถ้า review ซ้ำแล้วถูกข้าม)
+#[derive(Serialize)]
+struct BulkInsertResult {
+    ids: Vec<Option<ReviewId>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    duplicates: Vec<BulkDuplicate>,
+}
+
+/// review ที่ซ้ำ (ทั้งที่ถูกข้ามและที่ถูก flag)
+#[derive(Serialize)]
+struct BulkDuplicate {
+    index: usize,
+    duplicate_of: DuplicateMatch,
+}
+
+/// ผลการนำเข้าไฟล์: id ของแถวที่นำเข้าได้ตามลำดับในไฟล์ และแถวที่นำเข้าไม่ได้
+#[derive(Serialize, Default)]
+struct ImportResult {
+    /// จำนวนแถวข้อมูลที่อ่านได้ทั้งหมด
+    rows: u64,
+    ids: Vec<ReviewId>,
+    /// จำนวนแถวที่นำเข้าไม่ได้ (รายละเอียดใน `errors` สูงสุด `MAX_REPORTED_ERRORS` แถวแรก)
+    failed: u64,
+    errors: Vec<RowError>,
+}
+
+impl ImportResult {
+    fn fail(&mut self, row: u64, error: String) {
+        self.failed += 1;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(RowError { row, error });
+        }
+    }
+}
+
+/// วิธีเขียนของ `/reviews/bulk`
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum BulkMode {
+    /// เขียนทั้งชุดใน commit เดียว ถ้ามีรายการไหนผิดจะไม่มีรายการไหนถูกเขียน
+    #[default]
+    Atomic,
+    /// เขียนทีละรายการ รายการที่ผิดถูกรายงานแยกโดยไม่กระทบรายการอื่น
+    Partial,
+}
+
 #[derive(Deserialize)]
 struct ReviewsBulk {
     reviews: Vec<Review>,
+    #[serde(default)]
+    mode: BulkMode,
+}
+
+/// รายการที่เขียนสำเร็จใน mode `partial` (`index` คือลำดับใน `reviews` ที่ส่งมา เริ่มที่ 0)
+#[derive(Serialize)]
+struct BulkInserted {
+    index: usize,
+    id: ReviewId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicate_of: Option<DuplicateMatch>,
+}
+
+#[derive(Serialize)]
+struct BulkFailed {
+    index: usize,
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicate_of: Option<DuplicateMatch>,
+}
+
+#[derive(Serialize)]
+struct BulkPartialResult {
+    inserted: Vec<BulkInserted>,
+    failed: Vec<BulkFailed>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped: Vec<BulkDuplicate>,
+}
+
+/// วิธีจัดอันดับผลค้นหา
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum SearchMode {
+    /// ใกล้เคียงตามความหมาย (vector)
+    #[default]
+    Semantic,
+    /// BM25 บนคำใน title + body
+    Lexical,
+    /// รวมอันดับจากทั้งสองแบบด้วย reciprocal rank fusion
+    Hybrid,
 }
 
 #[derive(Deserialize)]
 struct SearchQuery {
     query: String,
+    #[serde(default)]
+    mode: SearchMode,
+    /// น้ำหนักของอันดับ semantic ใน mode `hybrid` ช่วง [0, 1] (ที่เหลือเป็นของ lexical)
+    hybrid_weight: Option<f32>,
+    /// จำนวนผลลัพธ์สูงสุด (ค่าเริ่มต้น `DEFAULT_TOP_K`)
+    top_k: Option<usize>,
+    /// คะแนนขั้นต่ำ (0, 1] ของผลจาก vector search ผลที่ `score` ต่ำกว่านี้จะถูกตัดทิ้ง
+    /// ใช้กับ mode `lexical` ไม่ได้เพราะคะแนน BM25 ไม่มีขอบบน
+    min_score: Option<f32>,
+    /// จำนวน partition ที่ probe: มากขึ้น recall ดีขึ้นแต่ช้าลง
+    nprobe: Option<usize>,
+    /// ค้นเฉพาะ review ของสินค้านี้
+    product_id: Option<String>,
+    /// ค้นเฉพาะ review ที่ `review_rating` อยู่ในช่วงนี้ (รวมขอบ)
+    min_rating: Option<u8>,
+    max_rating: Option<u8>,
+}
+
+/// ผลค้นหาหนึ่งรายการ: review พร้อม id, อันดับ และคะแนนความใกล้เคียง
+#[derive(Serialize)]
+struct SearchHit {
+    id: ReviewId,
+    version: u32,
+    rank: usize,
+    /// ยิ่งมากยิ่งตรง ความหมายขึ้นกับ mode: semantic = `1 / (1 + distance)` ในช่วง (0, 1],
+    /// lexical = คะแนน BM25, hybrid = คะแนน reciprocal rank fusion
+    score: f32,
+    /// ระยะ L2 ยกกำลังสองจาก query (มีเมื่อ review ถูกพบจาก vector search)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance: Option<f32>,
+    /// คะแนน BM25 (มีเมื่อ review ถูกพบจาก lexical search)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bm25: Option<f32>,
+    #[serde(flatten)]
+    review: Review,
+}
+
+/// ผลที่จัดอันดับแล้วแต่ยังไม่ได้ดึง metadata
+#[derive(Clone, Copy, Debug)]
+struct Ranked {
+    id: ReviewId,
+    score: f32,
+    distance: Option<f32>,
+    bm25: Option<f32>,
 }
 
 #[derive(Serialize)]
 struct SearchResult {
-    reviews: Vec<Review>,
+    reviews: Vec<SearchHit>,
+}
+
+#[derive(Serialize)]
+struct Metrics {
+    query_embedding_cache: CacheStats,
+    review_embedding_cache: CacheStats,
 }
 
 type EmbeddingVector = Vec<f32>;
 
-static VECTOR_FILE: Lazy<Mutex<File>> = Lazy::new(|| {
-    OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("data/reviews.index")
-        .expect("Cannot open vector index file")
-        .into()
+const VECTOR_PATH: &str = "data/reviews.index";
+const METADATA_PATH: &str = "data/reviews.jsonl";
+const TOMBSTONE_PATH: &str = "data/reviews.tombstones";
+const JOBS_DIR: &str = "data/jobs";
+const DEFAULT_TOP_K: usize = 5;
+const MAX_TOP_K: usize = 1000;
+/// จำนวน review ที่ embed และเขียนพร้อมกันหนึ่งชุดตอนนำเข้าไฟล์
+const IMPORT_BATCH_SIZE: usize = 32;
+/// ขนาดที่อ่านจากไฟล์ input ของงานนำเข้าต่อครั้ง
+const JOB_READ_CHUNK: usize = 64 * 1024;
+/// ขนาดไฟล์นำเข้าสูงสุด
+const MAX_IMPORT_BYTES: usize = 1 << 30;
+const MAX_REPORTED_ERRORS: usize = 1000;
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 1000;
+const DEFAULT_HYBRID_WEIGHT: f32 = 0.5;
+/// mode hybrid ดึง candidate จากแต่ละฝั่ง `top_k` × ค่านี้ก่อนรวมอันดับ
+const HYBRID_CANDIDATES: usize = 4;
+
+/// จำนวน embedding ของคำค้นที่ cache ไว้ ตั้งผ่าน env `QUERY_CACHE_SIZE`
+const DEFAULT_QUERY_CACHE_SIZE: usize = 10_000;
+/// จำนวน embedding ของเนื้อหา review ที่ cache ไว้ ตั้งผ่าน env `REVIEW_CACHE_SIZE`
+const DEFAULT_REVIEW_CACHE_SIZE: usize = 4_096;
+
+/// โฟลเดอร์ model (`model.onnx` + `tokenizer.json`) ตั้งผ่าน env `FASTEMBED_MODEL_DIR`
+/// ถ้าไม่ได้ตั้งจะไม่ยอม start ยกเว้นตั้ง `FASTEMBED_ALLOW_HASHING=1` เพื่อใช้ hashing embedder (dev/test)
+static EMBEDDER: Lazy<TextEmbedding> = Lazy::new(|| match std::env::var("FASTEMBED_MODEL_DIR") {
+    Ok(dir) => {
+        let model = TextEmbedding::from_dir(&dir)
+            .unwrap_or_else(|e| panic!("Cannot load embedding model from {}: {}", dir, e));
+        println!("Loaded embedding model from {} ({} dims)", dir, model.dim());
+        model
+    }
+    Err(_) if std::env::var("FASTEMBED_ALLOW_HASHING").is_ok_and(|v| v == "1") => {
+        eprintln!("⚠️ FASTEMBED_MODEL_DIR is not set, using hashing embedder (lexical only, not semantic)");
+        TextEmbedding::hashing(DEFAULT_HASHING_DIM)
+    }
+    Err(_) => panic!("FASTEMBED_MODEL_DIR is not set (set FASTEMBED_ALLOW_HASHING=1 to use the hashing embedder for dev/test)"),
 });
 
-static METADATA_FILE: Lazy<Mutex<File>> = Lazy::new(|| {
-    OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("data/reviews.jsonl")
-        .expect("Cannot open metadata file")
-        .into()
+static QUERY_CACHE: Lazy<EmbeddingCache> =
+    Lazy::new(|| EmbeddingCache::new(cache_size("QUERY_CACHE_SIZE", DEFAULT_QUERY_CACHE_SIZE)));
+
+/// review ที่ข้อความซ้ำกัน (เช่นนำเข้าไฟล์เดิมซ้ำ) ใช้ embedding จาก cache แทนการ embed ใหม่
+static REVIEW_CACHE: Lazy<EmbeddingCache> =
+    Lazy::new(|| EmbeddingCache::new(cache_size("REVIEW_CACHE_SIZE", DEFAULT_REVIEW_CACHE_SIZE)));
+
+fn cache_size(var: &str, default: usize) -> usize {
+    match std::env::var(var) {
+        Ok(size) => size.parse().unwrap_or_else(|_| panic!("{} must be a number of entries", var)),
+        Err(_) => default,
+    }
+}
+
+static INDEX: Lazy<Arc<RwLock<Index>>> = Lazy::new(|| {
+    let mut vectors = STORE.lock().unwrap().read_vectors().expect("Cannot read vector index file");
+    let tombstones = TOMBSTONES.read().unwrap();
+    vectors.retain(|(id, _)| !tombstones.contains(*id));
+    println!("Building spfresh index over {} vectors", vectors.len());
+    let index = Index::build(EMBEDDER.dim(), IndexConfig::default(), vectors);
+    println!("spfresh index ready: {} vectors in {} postings", index.len(), index.posting_count());
+    Arc::new(RwLock::new(index))
 });
 
-fn append_vector(vec: &EmbeddingVector) -> std::io::Result<()> {
-    let mut file = VECTOR_FILE.lock().unwrap();
-    for v in vec {
-        file.write_all(&v.to_le_bytes())?;
+/// index ใน memory ที่สร้างจาก metadata: attribute สำหรับ filter และ BM25 สำหรับ lexical search
+#[derive(Default)]
+struct MetadataIndex {
+    attributes: AttributeIndex,
+    lexical: Bm25Index,
+    content: ContentIndex,
+}
+
+impl MetadataIndex {
+    fn insert(&mut self, id: ReviewId, review: &Review) {
+        self.attributes.insert(id, review);
+        self.lexical.insert(id, &format!("{} {}", review.review_title, review.review_body));
+        self.content.insert(id, review);
+    }
+
+    fn remove(&mut self, id: ReviewId, review: &Review) {
+        self.attributes.remove(id);
+        self.content.remove(id, review);
+        self.lexical.remove(id, &format!("{} {}", review.review_title, review.review_body));
     }
-    file.flush()?;
-    Ok(())
 }
 
-fn append_metadata(review: &Review) -> std::io::Result<()> {
-    let mut file = METADATA_FILE.lock().unwrap();
-    let json_line = serde_json::to_string(review)?;
-    file.write_all(json_line.as_bytes())?;
-    file.write_all(b"\n")?;
-    file.flush()?;
-    Ok(())
+static METADATA_INDEX: Lazy<RwLock<MetadataIndex>> = Lazy::new(|| {
+    let mut metadata = MetadataIndex::default();
+    // record หลังสุดของแต่ละ id คือ version ปัจจุบัน
+    let mut latest = HashMap::new();
+    for stored in STORE.lock().unwrap().read_reviews().expect("Cannot read review metadata") {
+        latest.insert(stored.id, stored);
+    }
+    let tombstones = TOMBSTONES.read().unwrap();
+    for (id, stored) in latest {
+        if !tombstones.contains(id) {
+            metadata.insert(id, &stored.review);
+        }
+    }
+    println!("Indexed metadata of {} reviews (filters + BM25)", metadata.lexical.len());
+    RwLock::new(metadata)
+});
+
+/// id ของ review ที่ถูกลบ
+static TOMBSTONES: Lazy<RwLock<TombstoneLog>> = Lazy::new(|| {
+    let log = TombstoneLog::open(TOMBSTONE_PATH)
+        .unwrap_or_else(|e| panic!("Refusing to start, cannot open tombstone log: {}", e));
+    println!("Loaded {} tombstones from {}", log.len(), TOMBSTONE_PATH);
+    RwLock::new(log)
+});
+
+/// งานนำเข้าแบบ background ทั้งหมด (ดู [`jobs`])
+static JOBS: Lazy<Jobs> = Lazy::new(|| {
+    Jobs::open(JOBS_DIR).unwrap_or_else(|e| panic!("Refusing to start, cannot load import jobs from {}: {}", JOBS_DIR, e))
+});
+
+/// ทำงานนำเข้าทีละงานตามลำดับ
+static JOB_SLOTS: tokio::sync::Semaphore = tokio::sync::Semaphore::const_new(1);
+
+/// เปิดไฟล์ vector + metadata ตรวจ header และ recover ส่วนที่ยังไม่ commit
+/// ถ้า header ไม่ตรงกับ embedding model หรือไฟล์เสียจะไม่ยอม start
+static STORE: Lazy<Mutex<ReviewStore>> = Lazy::new(|| {
+    if compact::recover(VECTOR_PATH, METADATA_PATH).unwrap_or_else(|e| panic!("Refusing to start, cannot recover compaction: {}", e)) {
+        eprintln!("⚠️ Finished swapping in an interrupted compaction");
+    }
+    let (store, report) = ReviewStore::open(VECTOR_PATH, METADATA_PATH, EMBEDDER.dim(), EMBEDDER.model_id())
+        .unwrap_or_else(|e| panic!("Refusing to start, cannot open review store: {}", e));
+    if !report.is_clean() {
+        eprintln!(
+            "⚠️ Recovered review store: dropped {} uncommitted vector bytes and {} metadata bytes (saved to {}.orphaned)",
+            report.vector_bytes_truncated, report.metadata_bytes_truncated, METADATA_PATH
+        );
+    }
+    println!(
+        "Opened {} (format v{}, {} dims, {} reviews)",
+        VECTOR_PATH,
+        store.vectors().header().version,
+        store.dim(),
+        store.len()
+    );
+    Mutex::new(store)
+});
+
+/// เขียน vector + metadata ลงไฟล์แบบ atomic แล้วเพิ่มเข้า index
+/// ถือ lock ของ store จนเพิ่มเข้า index เสร็จ (เหมือน update และ delete) ไม่งั้น delete ที่มาก่อน
+/// เพิ่มเข้า index จะลบจาก index ไปก่อนแล้ว review ที่ถูกลบจะถูกใส่กลับเข้าไป
+fn store_review(review: &Review, embedding: EmbeddingVector) -> Result<ReviewId, (StatusCode, String)> {
+    let mut store = STORE.lock().unwrap();
+    let id = match store.append(review, &embedding) {
+        Ok(id) => id,
+        Err(e) => {
+            eprintln!("Review store write error: {}", e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Review store write error: {}", e)));
+        }
+    };
+
+    METADATA_INDEX.write().unwrap().insert(id, review);
+    INDEX.write().unwrap().insert(id, embedding);
+    drop(store);
+    Ok(id)
+}
+
+/// เขียนหลาย review ใน commit เดียวของ store แล้วเพิ่มเข้า index ทั้งหมด
+/// ถ้าเขียนไม่สำเร็จจะไม่มี review ไหนถูกเขียน ถือ lock ของ store จนเพิ่มเข้า index เสร็จเหมือน [`store_review`]
+fn store_reviews(reviews: &[Review], embeddings: Vec<EmbeddingVector>) -> Result<Vec<ReviewId>, (StatusCode, String)> {
+    store_reviews_after(reviews, embeddings, |_| Ok(()))
+}
+
+/// เหมือน [`store_reviews`] แต่เรียก `before_write` ด้วยช่วง id ที่ review จะได้ ก่อนเขียนและขณะถือ lock ของ store
+/// (ไม่มีใครได้ id ในช่วงนั้นก่อน) ถ้า `before_write` ไม่สำเร็จจะไม่เขียน
+fn store_reviews_after(
+    reviews: &[Review],
+    embeddings: Vec<EmbeddingVector>,
+    before_write: impl FnOnce(Range<ReviewId>) -> std::io::Result<()>,
+) -> Result<Vec<ReviewId>, (StatusCode, String)> {
+    let records: Vec<(&Review, &[f32])> = reviews.iter().zip(embeddings.iter().map(Vec::as_slice)).collect();
+    let mut store = STORE.lock().unwrap();
+    let first = store.vectors().next_id();
+    before_write(first..first + records.len() as u64).map_err(|e| {
+        eprintln!("Cannot prepare review store write: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, format!("Cannot prepare review store write: {} (nothing was inserted)", e))
+    })?;
+    let ids = store.append_all(&records).map_err(|e| {
+        eprintln!("Review store write error: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, format!("Review store write error: {} (nothing was inserted)", e))
+    })?;
+
+    let mut metadata = METADATA_INDEX.write().unwrap();
+    for (&id, review) in ids.iter().zip(reviews) {
+        metadata.insert(id, review);
+    }
+    drop(metadata);
+    let mut index = INDEX.write().unwrap();
+    for (&id, embedding) in ids.iter().zip(embeddings) {
+        index.insert(id, embedding);
+    }
+    drop(index);
+    drop(store);
+    Ok(ids)
+}
+
+/// embed เนื้อหา review หลัง [`text::preprocess`] ผ่าน [`REVIEW_CACHE`]
+fn embed(input: &str) -> Result<EmbeddingVector, (StatusCode, String)> {
+    embed_cached(&REVIEW_CACHE, input)
+}
+
+/// embed คำค้นแบบเดียวกับ [`embed`] ผ่าน [`QUERY_CACHE`]
+fn embed_query(input: &str) -> Result<EmbeddingVector, (StatusCode, String)> {
+    embed_cached(&QUERY_CACHE, input)
+}
+
+fn embed_cached(cache: &EmbeddingCache, input: &str) -> Result<EmbeddingVector, (StatusCode, String)> {
+    cache
+        .get_or_embed(&text::preprocess(input), |text| EMBEDDER.embed(text))
+        .map_err(|e| {
+            eprintln!("Embedding error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Embedding error: {}", e))
+        })
+}
+
+/// embed หลายข้อความเป็นชุดด้วย [`TextEmbedding::embed_batch`] เฉพาะข้อความที่ไม่มีใน [`REVIEW_CACHE`]
+/// ถ้าทั้งชุดไม่สำเร็จจะ embed ทีละข้อความ เพื่อให้ข้อความที่ embed ไม่ได้ได้ error ของตัวเองโดยไม่กระทบข้อความอื่น
+fn embed_batch(inputs: &[String]) -> Vec<Result<EmbeddingVector, String>> {
+    let preprocessed: Vec<String> = inputs.iter().map(|input| text::preprocess(input)).collect();
+    let mut results: Vec<Option<Result<EmbeddingVector, String>>> =
+        preprocessed.iter().map(|text| REVIEW_CACHE.get(text).map(Ok)).collect();
+    let missing: Vec<usize> = (0..results.len()).filter(|&i| results[i].is_none()).collect();
+    let texts: Vec<&str> = missing.iter().map(|&i| preprocessed[i].as_str()).collect();
+    let embedded: Vec<Result<EmbeddingVector, String>> = match EMBEDDER.embed_batch(&texts) {
+        Ok(vectors) => vectors.into_iter().map(Ok).collect(),
+        Err(e) => {
+            eprintln!("Batch embedding error, retrying one by one: {}", e);
+            texts
+                .iter()
+                .map(|text| EMBEDDER.embed(text).map_err(|e| format!("Embedding error: {}", e)))
+                .collect()
+        }
+    };
+    for (i, embedding) in missing.into_iter().zip(embedded) {
+        if let Ok(embedding) = &embedding {
+            REVIEW_CACHE.insert(preprocessed[i].clone(), embedding.clone());
+        }
+        results[i] = Some(embedding);
+    }
+    results.into_iter().map(|r| r.expect("every input is cached or embedded")).collect()
 }
 
-async fn insert_review(Json(review): Json<Review>) -> impl IntoResponse {
+/// review ที่มีอยู่แล้ว (ยังไม่ถูกลบ) ที่ `review` ซ้ำด้วย: exact ก่อน แล้วจึง near ถ้าตั้ง `max_distance`
+/// review ที่ hash ของเนื้อหาตรงกันถูกอ่านจาก store มาเทียบเนื้อหาจริง
+fn find_duplicate(
+    review: &Review,
+    embedding: &[f32],
+    max_distance: Option<f32>,
+) -> Result<Option<DuplicateMatch>, (StatusCode, String)> {
+    let candidates = METADATA_INDEX.read().unwrap().content.candidates(review);
+    if !candidates.is_empty() {
+        let store = STORE.lock().unwrap();
+        for id in candidates {
+            let stored = store.get(id).map_err(|e| {
+                eprintln!("Cannot read review {}: {}", id, e);
+                (StatusCode::INTERNAL_SERVER_ERROR, format!("Cannot read review {}: {}", id, e))
+            })?;
+            if stored.is_some_and(|stored| same_content(&stored.review, review)) {
+                return Ok(Some(DuplicateMatch { id, kind: DuplicateKind::Exact, score: 1.0 }));
+            }
+        }
+    }
+    let Some(max_distance) = max_distance else {
+        return Ok(None);
+    };
+    let params = SearchParams { top_k: 1, nprobe: None, max_distance: Some(max_distance) };
+    let nearest = INDEX.read().unwrap().search(embedding, &params).into_iter().next();
+    Ok(nearest.map(|(id, distance)| DuplicateMatch { id, kind: DuplicateKind::Near, score: 1.0 / (1.0 + distance) }))
+}
+
+async fn insert_review(
+    Query(duplicates): Query<DuplicateQuery>,
+    Json(review): Json<Review>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
     println!("Received insert_review request: {:?}", review);
 
+    let check = duplicates.check()?;
     let combined = format!("{} {}", review.review_title, review.review_body);
-    let embedding = embed(&combined);
+    let result = worker::run_embedding(move || {
+        let embedding = embed(&combined)?;
+        let duplicate_of = match check {
+            Some(check) => find_duplicate(&review, &embedding, check.max_distance)?,
+            None => None,
+        };
+        let (Some(check), Some(duplicate_of)) = (check, duplicate_of) else {
+            return Ok(InsertResult { id: Some(store_review(&review, embedding)?), duplicate_of: None });
+        };
+        match check.action {
+            DuplicateAction::Reject => Err((StatusCode::CONFLICT, format!("Review is a duplicate of {}", duplicate_of))),
+            DuplicateAction::Skip => Ok(InsertResult { id: None, duplicate_of: Some(duplicate_of) }),
+            DuplicateAction::Flag => Ok(InsertResult {
+                id: Some(store_review(&review, embedding)?),
+                duplicate_of: Some(duplicate_of),
+            }),
+        }
+    })
+    .await?;
+
+    Ok(AxumJson(result))
+}
+
+/// แก้ review: ต่อท้าย version ใหม่ (vector + metadata) แล้วแทนที่ version เดิมใน index
+/// ค้นหาจะเจอเฉพาะ version ล่าสุด คืน 404 ถ้าไม่มี review id นี้หรือถูกลบไปแล้ว
+async fn update_review(Path(id): Path<ReviewId>, Json(review): Json<Review>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    println!("Received update_review request: {} {:?}", id, review);
+
+    worker::run_embedding(move || {
+        let not_found = || (StatusCode::NOT_FOUND, format!("Review {} not found", id));
+        if TOMBSTONES.read().unwrap().contains(id) {
+            return Err(not_found());
+        }
+
+        let combined = format!("{} {}", review.review_title, review.review_body);
+        let embedding = embed(&combined)?;
+
+        // ถือ lock ของ store ไว้จนอัปเดต index เสร็จ เพื่อให้การแก้ id เดียวกันพร้อมกันเข้า index ตามลำดับ version
+        let mut store = STORE.lock().unwrap();
+        let write_error = |e: std::io::Error| {
+            eprintln!("Review store write error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Review store write error: {}", e))
+        };
+        // เช็คอีกครั้งเพราะอาจถูกลบระหว่าง embed (delete เขียน tombstone ขณะถือ lock ของ store)
+        if TOMBSTONES.read().unwrap().contains(id) {
+            return Err(not_found());
+        }
+        let previous = store.get(id).map_err(write_error)?.ok_or_else(not_found)?;
+        let version = store
+            .append_version(id, &review, &embedding)
+            .map_err(write_error)?
+            .ok_or_else(not_found)?;
+
+        // ปล่อย lock ของ metadata ก่อนเอา lock ของ index (search เอา index ก่อน metadata)
+        {
+            let mut metadata = METADATA_INDEX.write().unwrap();
+            metadata.remove(id, &previous.review);
+            metadata.insert(id, &review);
+        }
+        INDEX.write().unwrap().insert(id, embedding);
+
+        Ok(AxumJson(UpdateResult { id, version }))
+    })
+    .await
+}
+
+/// ทุก version ของ review คืน 404 ถ้าไม่มี review id นี้หรือถูกลบไปแล้ว
+async fn review_history(Path(id): Path<ReviewId>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    worker::run_blocking(move || {
+        let not_found = || (StatusCode::NOT_FOUND, format!("Review {} not found", id));
+        if TOMBSTONES.read().unwrap().contains(id) {
+            return Err(not_found());
+        }
+
+        let versions = STORE.lock().unwrap().history(id).map_err(|e| {
+            eprintln!("Cannot read review {}: {}", id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Cannot read review {}: {}", id, e))
+        })?;
+        if versions.is_empty() {
+            return Err(not_found());
+        }
+
+        Ok(AxumJson(ReviewHistory { id, versions }))
+    })
+    .await
+}
+
+/// version ล่าสุดของ review คืน 404 ถ้าไม่มี review id นี้หรือถูกลบไปแล้ว
+async fn get_review(Path(id): Path<ReviewId>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    worker::run_blocking(move || {
+        let not_found = || (StatusCode::NOT_FOUND, format!("Review {} not found", id));
+        if TOMBSTONES.read().unwrap().contains(id) {
+            return Err(not_found());
+        }
+
+        let stored = STORE.lock().unwrap().get(id).map_err(|e| {
+            eprintln!("Cannot read review {}: {}", id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Cannot read review {}: {}", id, e))
+        })?;
+
+        stored.map(AxumJson).ok_or_else(not_found)
+    })
+    .await
+}
+
+/// review ที่ยังไม่ถูกลบเรียงตาม id ทีละหน้า หา id จาก attribute index ใน memory
+/// แล้วอ่านเฉพาะบรรทัดของหน้านั้นผ่าน offset index (ไม่สแกน `reviews.jsonl`)
+async fn list_reviews(Query(query): Query<ListQuery>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    worker::run_blocking(move || {
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if limit == 0 || limit > MAX_PAGE_SIZE {
+            return Err((StatusCode::BAD_REQUEST, format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+        }
+        let (min_rating, max_rating) = match query.review_rating {
+            Some(_) if query.min_rating.is_some() || query.max_rating.is_some() => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "review_rating cannot be combined with min_rating or max_rating".to_string(),
+                ));
+            }
+            Some(rating) => (Some(rating), Some(rating)),
+            None => (query.min_rating, query.max_rating),
+        };
+        let filter = review_filter(query.product_id, min_rating, max_rating)?;
+
+        // ขอเกินหนึ่งรายการเพื่อรู้ว่ามีหน้าถัดไปหรือไม่
+        let mut ids: Vec<ReviewId> = METADATA_INDEX
+            .read()
+            .unwrap()
+            .attributes
+            .ids_after(query.cursor, &filter)
+            .take(limit + 1)
+            .collect();
+        let next_cursor = if ids.len() > limit {
+            ids.truncate(limit);
+            ids.last().copied()
+        } else {
+            None
+        };
+
+        let store = STORE.lock().unwrap();
+        let mut reviews = Vec::with_capacity(ids.len());
+        for id in ids {
+            match store.get(id) {
+                Ok(Some(stored)) => reviews.push(stored),
+                Ok(None) => eprintln!("Review {} is in the metadata index but not in metadata", id),
+                Err(e) => {
+                    eprintln!("Cannot read review {}: {}", id, e);
+                    return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Cannot read review {}: {}", id, e)));
+                }
+            }
+        }
+
+        Ok(AxumJson(ReviewPage { reviews, next_cursor }))
+    })
+    .await
+}
+
+/// soft delete: ต่อท้าย tombstone แล้วเอา review ออกจาก index ไฟล์ข้อมูลเดิมไม่ถูกแก้
+/// ลบซ้ำได้ (idempotent) คืน 404 ถ้าไม่มี review id นี้
+async fn delete_review(Path(id): Path<ReviewId>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    println!("Received delete_review request: {}", id);
+
+    worker::run_blocking(move || {
+        // ถือ lock ของ store จนเอาออกจาก index เสร็จ เพื่อไม่ให้ update ที่กำลังเขียน version ใหม่ใส่ id นี้กลับเข้า index
+        let store = STORE.lock().unwrap();
+        if let Some(stored) = delete_stored(&store, &TOMBSTONES, id)? {
+            METADATA_INDEX.write().unwrap().remove(id, &stored.review);
+            INDEX.write().unwrap().remove(id);
+        }
+        drop(store);
+
+        Ok(StatusCode::NO_CONTENT)
+    })
+    .await
+}
+
+/// เขียน tombstone ของ review `id` (ต้องถือ lock ของ store อยู่) คืน review ที่เพิ่งถูกลบเพื่อเอาออกจาก index
+/// หรือ `None` ถ้าถูกลบไปก่อนแล้ว ซึ่ง [`delete_review`] ก็ตอบ `204` เหมือนกัน
+fn delete_stored(store: &ReviewStore, tombstones: &RwLock<TombstoneLog>, id: ReviewId) -> Result<Option<StoredReview>, (StatusCode, String)> {
+    // record ของ review ที่ลบแล้วอาจหายไปหลัง compaction จึงต้องเช็ค tombstone ก่อนหาใน store
+    if tombstones.read().unwrap().contains(id) {
+        return Ok(None);
+    }
+    let stored = match store.get(id) {
+        Ok(Some(stored)) => stored,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, format!("Review {} not found", id))),
+        Err(e) => {
+            eprintln!("Cannot read review {}: {}", id, e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Cannot read review {}: {}", id, e)));
+        }
+    };
+
+    let newly_deleted = tombstones.write().unwrap().delete(id).map_err(|e| {
+        eprintln!("Tombstone write error: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, format!("Tombstone write error: {}", e))
+    })?;
+    Ok(newly_deleted.then_some(stored))
+}
+
+/// เพิ่มหลาย review ตาม `mode`: `atomic` (ค่าเริ่มต้น) embed ทุกรายการก่อนแล้วเขียนใน commit เดียว
+/// ถ้าผิดจะไม่มีรายการไหนถูกเขียนและ error บอกลำดับของรายการ `partial` คืนผลแยกรายการ
+async fn insert_bulk_reviews(
+    Query(duplicates): Query<DuplicateQuery>,
+    Json(payload): Json<ReviewsBulk>,
+) -> Result<Response, (StatusCode, String)> {
+    println!("Received bulk insert request with {} reviews ({:?})", payload.reviews.len(), payload.mode);
+
+    let check = duplicates.check()?;
+    worker::run_embedding(move || {
+        let inputs: Vec<String> = payload
+            .reviews
+            .iter()
+            .map(|review| format!("{} {}", review.review_title, review.review_body))
+            .collect();
+        let embeddings = embed_batch(&inputs);
+
+        match payload.mode {
+            BulkMode::Atomic => {
+                let embeddings = embeddings
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, embedding)| {
+                        embedding.map_err(|e| {
+                            (StatusCode::INTERNAL_SERVER_ERROR, format!("Review #{}: {} (nothing was inserted)", i, e))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let result = match check {
+                    None => BulkInsertResult {
+                        ids: store_reviews(&payload.reviews, embeddings)?.into_iter().map(Some).collect(),
+                        duplicates: Vec::new(),
+                    },
+                    Some(check) => store_reviews_deduped(payload.reviews, embeddings, check)?,
+                };
+                Ok(AxumJson(result).into_response())
+            }
+            BulkMode::Partial => {
+                let mut result = BulkPartialResult {
+                    inserted: Vec::new(),
+                    failed: Vec::new(),
+                    skipped: Vec::new(),
+                };
+                for (index, (review, embedding)) in payload.reviews.iter().zip(embeddings).enumerate() {
+                    let embedding = match embedding {
+                        Ok(embedding) => embedding,
+                        Err(error) => {
+                            result.failed.push(BulkFailed { index, error, duplicate_of: None });
+                            continue;
+                        }
+                    };
+                    // เขียนทีละรายการ review ก่อนหน้าใน request เดียวกันจึงอยู่ใน index แล้ว
+                    let duplicate_of = match check.map(|c| find_duplicate(review, &embedding, c.max_distance)) {
+                        Some(Ok(duplicate_of)) => duplicate_of,
+                        Some(Err((_, error))) => {
+                            result.failed.push(BulkFailed { index, error, duplicate_of: None });
+                            continue;
+                        }
+                        None => None,
+                    };
+                    match (check.map(|c| c.action), duplicate_of) {
+                        (Some(DuplicateAction::Reject), Some(duplicate_of)) => result.failed.push(BulkFailed {
+                            index,
+                            error: format!("Review is a duplicate of {}", duplicate_of),
+                            duplicate_of: Some(duplicate_of),
+                        }),
+                        (Some(DuplicateAction::Skip), Some(duplicate_of)) => {
+                            result.skipped.push(BulkDuplicate { index, duplicate_of })
+                        }
+                        _ => match store_review(review, embedding) {
+                            Ok(id) => result.inserted.push(BulkInserted { index, id, duplicate_of }),
+                            Err((_, error)) => result.failed.push(BulkFailed { index, error, duplicate_of }),
+                        },
+                    }
+                }
+                Ok(AxumJson(result).into_response())
+            }
+        }
+    })
+    .await
+}
+
+/// bulk แบบ atomic ที่ตรวจ review ซ้ำ ทั้งกับ review ที่มีอยู่และกับรายการก่อนหน้าใน request เดียวกัน
+/// ถ้า `duplicates=reject` และมีรายการซ้ำจะตอบ `409` โดยไม่มี review ไหนถูกเขียน
+fn store_reviews_deduped(
+    reviews: Vec<Review>,
+    embeddings: Vec<EmbeddingVector>,
+    check: DuplicateCheck,
+) -> Result<BulkInsertResult, (StatusCode, String)> {
+    let plan = dedupe::plan_bulk(&reviews, &embeddings, check.action, check.max_distance, |review, embedding| {
+        find_duplicate(review, embedding, check.max_distance)
+    })?;
 
-    if let Err(e) = append_vector(&embedding) {
-        eprintln!("Vector write error: {}", e);
-        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Vector write error: {}", e));
+    let mut write = vec![false; reviews.len()];
+    for &index in &plan.write {
+        write[index] = true;
     }
+    let (kept_reviews, kept_embeddings): (Vec<Review>, Vec<EmbeddingVector>) =
+        reviews.into_iter().zip(embeddings).zip(write.iter().copied()).filter(|(_, write)| *write).map(|(kept, _)| kept).unzip();
+    let mut ids = vec![None; write.len()];
+    for (&index, id) in plan.write.iter().zip(store_reviews(&kept_reviews, kept_embeddings)?) {
+        ids[index] = Some(id);
+    }
+    let duplicates =
+        plan.duplicates(&ids).into_iter().map(|(index, duplicate_of)| BulkDuplicate { index, duplicate_of }).collect();
+    Ok(BulkInsertResult { ids, duplicates })
+}
+
+/// นำเข้า review จากไฟล์ CSV หรือ JSONL ที่อัปโหลดแบบ multipart (ดู [`import`])
+///
+/// field ของ form: `format` (`csv` / `jsonl` ถ้าไม่ระบุจะดูจากนามสกุลไฟล์), `mapping` (JSON ของ
+/// [`ColumnMapping`]) และ `file` ซึ่งต้องมาหลังสอง field แรก ไฟล์ถูกอ่านทีละ chunk
+/// แล้ว embed และเขียนทีละ `IMPORT_BATCH_SIZE` แถว แถวที่ไม่ผ่านการตรวจ embed หรือเขียนไม่ได้ถูกรายงานใน `errors`
+async fn import_reviews(mut multipart: Multipart) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let mut format = None;
+    let mut mapping = ColumnMapping::default();
+    let mut result = None;
+
+    while let Some(mut field) = multipart.next_field().await.map_err(|e| bad_request(e.to_string()))? {
+        match field.name() {
+            Some("format" | "mapping") => read_import_option(field, &mut format, &mut mapping).await?,
+            Some("file") => {
+                let format = file_format(format, &field)?;
+                println!("Received import of {:?} ({:?})", field.file_name(), format);
+
+                // จองคิวไว้ทั้งไฟล์ ชุดหลัง ๆ จะรอ worker แทนที่จะได้ 429 กลางทาง
+                let slot = worker::reserve()?;
+                let mut reader = RowReader::new(format, mapping.clone());
+                let mut imported = ImportResult::default();
+                let mut rows = Vec::new();
+                let mut batch = Vec::new();
+                loop {
+                    let chunk = field.chunk().await.map_err(|e| bad_request(e.to_string()))?;
+                    match &chunk {
+                        Some(chunk) => reader.feed(chunk, &mut rows).map_err(bad_request)?,
+                        None => reader.finish(&mut rows).map_err(bad_request)?,
+                    }
+                    for (row, review) in rows.drain(..) {
+                        imported.rows += 1;
+                        match review {
+                            Ok(review) => batch.push((row, review)),
+                            Err(e) => imported.fail(row, e),
+                        }
+                        if batch.len() == IMPORT_BATCH_SIZE {
+                            import_batch(&slot, std::mem::take(&mut batch), &mut imported).await?;
+                        }
+                    }
+                    if chunk.is_none() {
+                        break;
+                    }
+                }
+                import_batch(&slot, batch, &mut imported).await?;
+                println!("Imported {} of {} rows", imported.ids.len(), imported.rows);
+                result = Some(imported);
+            }
+            _ => {}
+        }
+    }
+
+    result
+        .map(AxumJson)
+        .ok_or_else(|| bad_request("Missing file field".to_string()))
+}
 
-    if let Err(e) = append_metadata(&review) {
-        eprintln!("Metadata write error: {}", e);
-        return (StatusCode::INTERNAL_SERVER_ERROR, format!("Metadata write error: {}", e));
+/// อ่าน field `format` หรือ `mapping` ของ form นำเข้า
+async fn read_import_option(field: Field<'_>, format: &mut Option<Format>, mapping: &mut ColumnMapping) -> Result<(), (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let name = field.name().unwrap_or_default().to_string();
+    let value = field.text().await.map_err(|e| bad_request(e.to_string()))?;
+    if name == "format" {
+        *format = Some(Format::from_name(&value).ok_or_else(|| bad_request(format!("Unknown format {:?}, use csv or jsonl", value)))?);
+    } else {
+        *mapping = serde_json::from_str(&value).map_err(|e| bad_request(format!("Invalid mapping: {}", e)))?;
     }
+    Ok(())
+}
 
-    (StatusCode::OK, "Review inserted".to_string())
+/// format ที่ระบุมา หรือเดาจากนามสกุลของไฟล์
+fn file_format(format: Option<Format>, field: &Field<'_>) -> Result<Format, (StatusCode, String)> {
+    format
+        .or_else(|| field.file_name().and_then(Format::from_file_name))
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Cannot tell the file format, set format to csv or jsonl".to_string()))
 }
 
-async fn insert_bulk_reviews(Json(payload): Json<ReviewsBulk>) -> impl IntoResponse {
-    println!("Received bulk insert request with {} reviews", payload.reviews.len());
+/// embed review ในชุดแล้วเขียนลง store (ใน worker ของคิวที่จองไว้) แถวที่ embed หรือเขียนไม่ได้ถูกรายงานแทน
+async fn import_batch(
+    slot: &worker::QueueSlot,
+    batch: Vec<(u64, Review)>,
+    result: &mut ImportResult,
+) -> Result<(), (StatusCode, String)> {
+    let written = slot.run(move || Ok(embed_and_store(batch))).await?;
+    for (row, id) in written {
+        match id {
+            Ok(id) => result.ids.push(id),
+            Err(e) => result.fail(row, e),
+        }
+    }
+    Ok(())
+}
 
-    for (i, review) in payload.reviews.iter().enumerate() {
-        println!("Processing review #{}: {:?}", i, review);
+/// review ในชุดที่ embed แล้ว แยกจากแถวที่ embed ไม่ได้
+struct EmbeddedRows {
+    rows: Vec<u64>,
+    reviews: Vec<Review>,
+    embeddings: Vec<EmbeddingVector>,
+    failed: Vec<(u64, String)>,
+}
 
-        let combined = format!("{} {}", review.review_title, review.review_body);
-        let embedding = embed(&combined);
+fn embed_rows(batch: Vec<(u64, Review)>) -> EmbeddedRows {
+    let inputs: Vec<String> = batch
+        .iter()
+        .map(|(_, review)| format!("{} {}", review.review_title, review.review_body))
+        .collect();
+    let mut embedded = EmbeddedRows {
+        rows: Vec::new(),
+        reviews: Vec::new(),
+        embeddings: Vec::new(),
+        failed: Vec::new(),
+    };
+    for ((row, review), embedding) in batch.into_iter().zip(embed_batch(&inputs)) {
+        match embedding {
+            Ok(embedding) => {
+                embedded.rows.push(row);
+                embedded.reviews.push(review);
+                embedded.embeddings.push(embedding);
+            }
+            Err(e) => embedded.failed.push((row, e)),
+        }
+    }
+    embedded
+}
 
-        if let Err(e) = append_vector(&embedding) {
-            eprintln!("Vector write error: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Vector write error: {}", e));
+/// embed review ในชุดแล้วเขียนทุกแถวที่ embed ได้ใน commit เดียวของ store
+/// ถ้า commit ไม่สำเร็จทุกแถวในชุดได้ error ของ store (ไม่มีแถวไหนถูกเขียน)
+fn embed_and_store(batch: Vec<(u64, Review)>) -> Vec<(u64, Result<ReviewId, String>)> {
+    let EmbeddedRows { rows, reviews, embeddings, failed } = embed_rows(batch);
+    let mut written: Vec<_> = failed.into_iter().map(|(row, e)| (row, Err(e))).collect();
+    if !reviews.is_empty() {
+        match store_reviews(&reviews, embeddings) {
+            Ok(ids) => written.extend(rows.into_iter().zip(ids.into_iter().map(Ok))),
+            Err((_, e)) => written.extend(rows.into_iter().map(|row| (row, Err(e.clone())))),
         }
+    }
+    written.sort_by_key(|&(row, _)| row);
+    written
+}
+
+/// สร้างงานนำเข้าแบบ background จากไฟล์ที่อัปโหลด (field เหมือน `/reviews/import`)
+/// เขียนไฟล์ลง `data/jobs/` แล้วคืน `202` พร้อมสถานะของงานทันที ดูความคืบหน้าได้ที่ `GET /jobs/{id}`
+async fn create_job(mut multipart: Multipart) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let mut format = None;
+    let mut mapping = ColumnMapping::default();
 
-        if let Err(e) = append_metadata(review) {
-            eprintln!("Metadata write error: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("Metadata write error: {}", e));
+    while let Some(mut field) = multipart.next_field().await.map_err(|e| bad_request(e.to_string()))? {
+        match field.name() {
+            Some("format" | "mapping") => read_import_option(field, &mut format, &mut mapping).await?,
+            Some("file") => {
+                let format = file_format(format, &field)?;
+                let file_name = field.file_name().map(str::to_string);
+                let (id, input_path) = JOBS.reserve();
+
+                let spooled = async {
+                    let mut input = tokio::fs::File::create(&input_path).await.map_err(|e| e.to_string())?;
+                    while let Some(chunk) = field.chunk().await.map_err(|e| e.to_string())? {
+                        input.write_all(&chunk).await.map_err(|e| e.to_string())?;
+                    }
+                    input.sync_all().await.map_err(|e| e.to_string())
+                };
+                if let Err(e) = spooled.await {
+                    let _ = tokio::fs::remove_file(&input_path).await;
+                    return Err(bad_request(format!("Cannot receive file: {}", e)));
+                }
+
+                let job = worker::run_blocking(move || {
+                    JOBS.create(id, file_name, format, mapping).map_err(|e| {
+                        eprintln!("Cannot create import job: {}", e);
+                        (StatusCode::INTERNAL_SERVER_ERROR, format!("Cannot create import job: {}", e))
+                    })
+                })
+                .await?;
+                println!("Created import job {} ({:?})", id, format);
+                spawn_job(job.clone());
+                return Ok((StatusCode::ACCEPTED, AxumJson(job.snapshot())));
+            }
+            _ => {}
         }
     }
 
-    (StatusCode::OK, "Bulk reviews inserted".to_string())
+    Err(bad_request("Missing file field".to_string()))
 }
 
-async fn search_reviews(Json(query): Json<SearchQuery>) -> Result<impl IntoResponse, (StatusCode, String)> {
-    println!("Received search query: {:?}", query.query);
+async fn get_job(Path(id): Path<JobId>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    JOBS.get(id)
+        .map(|job| AxumJson(job.snapshot()))
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Job {} not found", id)))
+}
 
-    let q_embedding = embed(&query.query);
+/// ยกเลิกงาน review ที่นำเข้าไปแล้วยังอยู่ คืน 409 ถ้างานจบไปแล้ว
+async fn cancel_job(Path(id): Path<JobId>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    worker::run_blocking(move || {
+        let job = JOBS.get(id).ok_or_else(|| (StatusCode::NOT_FOUND, format!("Job {} not found", id)))?;
+        let cancelled = job.cancel().map_err(|e| {
+            eprintln!("Cannot cancel job {}: {}", id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Cannot cancel job {}: {}", id, e))
+        })?;
+        if !cancelled {
+            return Err((StatusCode::CONFLICT, format!("Job {} has already finished", id)));
+        }
+        Ok(AxumJson(job.snapshot()))
+    })
+    .await
+}
 
-    let matched_indices = search(&q_embedding);
+/// เข้าคิวงานนำเข้า embed และเขียน review ใน blocking thread ของ tokio
+fn spawn_job(job: Arc<JobHandle>) {
+    tokio::spawn(async move {
+        let _slot = JOB_SLOTS.acquire().await.unwrap();
+        if let Err(e) = tokio::task::spawn_blocking(move || run_job(&job)).await {
+            eprintln!("Import job panicked: {}", e);
+        }
+    });
+}
 
-    let file = match File::open("data/reviews.jsonl") {
-        Ok(f) => f,
+fn run_job(job: &JobHandle) {
+    let id = job.snapshot().id;
+    if !job.start() {
+        // ถูกยกเลิกตอนรอคิว ลบ input ตอนนี้ (cancel ไม่ลบเองเพราะ worker อาจกำลังจะเริ่ม)
+        if let Err(e) = job.finish(JobStatus::Cancelled) {
+            eprintln!("⚠️ Cannot save import job {}: {}", id, e);
+        }
+        return;
+    }
+    if let Err(e) = job.save() {
+        eprintln!("⚠️ Cannot save import job {}: {}", id, e);
+    }
+    println!("Running import job {}", id);
+
+    let status = match import_job(job) {
+        Ok(true) => JobStatus::Completed,
+        Ok(false) => JobStatus::Cancelled,
         Err(e) => {
-            eprintln!("Cannot open metadata file: {}", e);
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Cannot open metadata file: {}", e)));
+            eprintln!("⚠️ Import job {} failed: {}", id, e);
+            job.update(|j| j.error = Some(e));
+            JobStatus::Failed
         }
     };
-    let reader = BufReader::new(file);
+    if let Err(e) = job.finish(status) {
+        eprintln!("⚠️ Cannot save import job {}: {}", id, e);
+    }
+    let job = job.snapshot();
+    println!("Import job {} {:?}: {} imported, {} failed of {} rows", id, job.status, job.imported, job.failed, job.processed);
+}
 
-    let mut results = vec![];
+/// นำเข้าแถวที่ยังไม่ได้ทำของงาน คืน `false` ถ้าถูกยกเลิกกลางทาง
+///
+/// ผลของแต่ละชุด (รวมแถวที่ผิด) ถูกนับและบันทึกหลังเขียนชุดนั้นเสร็จ `processed` จึงไม่เลยแถวที่ยังไม่ได้เขียน
+/// ชุดที่ค้างตอน process ตายถูกจัดการก่อนเปิดรับ request ด้วย [`settle_pending_batch`]
+fn import_job(job: &JobHandle) -> Result<bool, String> {
+    let start = job.snapshot();
+    let mut input = std::fs::File::open(job.input_path()).map_err(|e| format!("Cannot open input: {}", e))?;
+    let mut reader = RowReader::new(start.format, start.mapping.clone());
 
-    for (i, line) in reader.lines().enumerate() {
-        if matched_indices.contains(&i) {
-            if let Ok(json_str) = line {
-                if let Ok(review) = serde_json::from_str::<Review>(&json_str) {
-                    results.push(review);
-                } else {
-                    eprintln!("Failed to parse review at line {}", i);
+    let mut buf = vec![0u8; JOB_READ_CHUNK];
+    let mut rows = Vec::new();
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    loop {
+        if job.is_cancelled() {
+            return Ok(false);
+        }
+        let n = input.read(&mut buf).map_err(|e| format!("Cannot read input: {}", e))?;
+        if n == 0 {
+            reader.finish(&mut rows)?;
+        } else {
+            reader.feed(&buf[..n], &mut rows)?;
+        }
+
+        for (row, review) in rows.drain(..) {
+            if row <= start.processed {
+                continue;
+            }
+            batch.push((row, review));
+            if batch.len() == IMPORT_BATCH_SIZE {
+                import_job_batch(job, &mut batch)?;
+                if job.is_cancelled() {
+                    return Ok(false);
                 }
             }
         }
+        if n == 0 {
+            break;
+        }
+    }
+    import_job_batch(job, &mut batch)?;
+    Ok(true)
+}
+
+/// embed และเขียน review หนึ่งชุดของงานโดยถือ worker ตลอดชุด แล้วบันทึกสถานะ
+/// ช่วง id ของชุดถูกบันทึกใน `pending` ก่อนเขียน ถ้าเขียนลง store ไม่สำเร็จจะคืน error
+/// โดยไม่เลื่อน `processed` งานจึงทำต่อจากชุดนี้ได้
+fn import_job_batch(job: &JobHandle, batch: &mut Vec<Row>) -> Result<(), String> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    worker::with_worker(|| write_job_batch(job, batch))
+}
+
+fn write_job_batch(job: &JobHandle, batch: &mut Vec<Row>) -> Result<(), String> {
+    let Some(last_row) = batch.iter().map(|(row, _)| *row).max() else {
+        return Ok(());
+    };
+    let mut failed = Vec::new();
+    let mut valid = Vec::with_capacity(batch.len());
+    for (row, review) in batch.drain(..) {
+        match review {
+            Ok(review) => valid.push((row, review)),
+            Err(error) => failed.push(RowError { row, error }),
+        }
+    }
+    let embedded = embed_rows(valid);
+    failed.extend(embedded.failed.into_iter().map(|(row, error)| RowError { row, error }));
+    failed.sort_by_key(|e| e.row);
+
+    let mut pending = PendingBatch { ids: 0..0, last_row, failed };
+    if !embedded.reviews.is_empty() {
+        let written = store_reviews_after(&embedded.reviews, embedded.embeddings, |ids| {
+            pending.ids = ids;
+            job.update(|j| j.pending = Some(pending.clone()));
+            job.save()
+        });
+        if let Err((_, e)) = written {
+            job.update(|j| j.pending = None);
+            return Err(e);
+        }
+    }
+    job.update(|j| {
+        j.pending = None;
+        j.complete(pending);
+    });
+    job.save().map_err(|e| format!("Cannot save job state: {}", e))
+}
+
+/// ชุดที่ค้างอยู่ใน `pending` ตอน process ตาย ถูกเขียนแล้วถ้า id ถัดไปของ store เลยจุดเริ่มของชุดไปแล้ว
+/// (id ถูกจองขณะถือ lock ของ store) ต้องเรียกก่อนเปิดรับ request ไม่งั้น review อื่นอาจได้ id ของชุดที่ไม่ได้เขียน
+fn settle_pending_batch(job: &JobHandle) -> std::io::Result<()> {
+    let Some(pending) = job.snapshot().pending else {
+        return Ok(());
+    };
+    let written = STORE.lock().unwrap().vectors().next_id() > pending.ids.start;
+    job.update(|j| {
+        j.pending = None;
+        if written {
+            j.complete(pending);
+        }
+    });
+    job.save()
+}
+
+/// แปลง option ของ request เป็น `SearchParams` ของ spfresh
+/// `min_score` แปลงเป็นระยะสูงสุดจาก `score = 1 / (1 + distance)`
+fn search_params(query: &SearchQuery) -> Result<SearchParams, (StatusCode, String)> {
+    let top_k = query.top_k.unwrap_or(DEFAULT_TOP_K);
+    if top_k == 0 || top_k > MAX_TOP_K {
+        return Err((StatusCode::BAD_REQUEST, format!("top_k must be between 1 and {}", MAX_TOP_K)));
+    }
+
+    let max_distance = match query.min_score {
+        None => None,
+        Some(_) if query.mode == SearchMode::Lexical => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "min_score applies to vector search only, use mode semantic or hybrid".to_string(),
+            ));
+        }
+        Some(s) if s > 0.0 && s <= 1.0 => Some(1.0 / s - 1.0),
+        Some(_) => return Err((StatusCode::BAD_REQUEST, "min_score must be in (0, 1]".to_string())),
+    };
+
+    if query.nprobe == Some(0) {
+        return Err((StatusCode::BAD_REQUEST, "nprobe must be at least 1".to_string()));
+    }
+
+    Ok(SearchParams {
+        top_k,
+        nprobe: query.nprobe,
+        max_distance,
+    })
+}
+
+fn review_filter(product_id: Option<String>, min_rating: Option<u8>, max_rating: Option<u8>) -> Result<ReviewFilter, (StatusCode, String)> {
+    if let (Some(min), Some(max)) = (min_rating, max_rating)
+        && min > max
+    {
+        return Err((StatusCode::BAD_REQUEST, "min_rating must not be greater than max_rating".to_string()));
+    }
+
+    Ok(ReviewFilter {
+        product_id,
+        min_rating,
+        max_rating,
+    })
+}
+
+/// vector search เรียงจากใกล้ไปไกล (ระยะเท่ากันเรียงตาม id)
+/// ถ้ามี filter จะกรองระหว่างสแกน candidate ใน spfresh เพื่อให้ยังได้ครบ `top_k`
+fn semantic_matches(
+    index: &Index,
+    query: &[f32],
+    params: &SearchParams,
+    filter: &ReviewFilter,
+    attributes: &AttributeIndex,
+) -> Vec<Ranked> {
+    let matches = if filter.is_empty() {
+        index.search(query, params)
+    } else {
+        index.search_filtered(query, params, |id| filter.matches(attributes, id))
+    };
+    matches
+        .into_iter()
+        .map(|(id, distance)| Ranked {
+            id,
+            score: 1.0 / (1.0 + distance),
+            distance: Some(distance),
+            bm25: None,
+        })
+        .collect()
+}
+
+fn lexical_matches(lexical: &Bm25Index, query: &str, top_k: usize, filter: &ReviewFilter, attributes: &AttributeIndex) -> Vec<Ranked> {
+    lexical
+        .search(query, top_k, |id| filter.is_empty() || filter.matches(attributes, id))
+        .into_iter()
+        .map(|(id, score)| Ranked {
+            id,
+            score,
+            distance: None,
+            bm25: Some(score),
+        })
+        .collect()
+}
+
+/// รวมอันดับจากสองฝั่งด้วย [`lexical::fuse`] โดยเก็บระยะและคะแนน BM25 ของแต่ละผลไว้
+fn fuse(semantic: Vec<Ranked>, lexical: Vec<Ranked>, weight: f32, top_k: usize) -> Vec<Ranked> {
+    let semantic_ids: Vec<ReviewId> = semantic.iter().map(|r| r.id).collect();
+    let lexical_ids: Vec<ReviewId> = lexical.iter().map(|r| r.id).collect();
+    let mut matches: HashMap<ReviewId, Ranked> = semantic.into_iter().map(|r| (r.id, r)).collect();
+    for r in lexical {
+        matches.entry(r.id).or_insert(r).bm25 = r.bm25;
+    }
+
+    lexical::fuse(&semantic_ids, &lexical_ids, weight, top_k)
+        .into_iter()
+        .map(|(id, score)| Ranked { score, ..matches[&id] })
+        .collect()
+}
+
+/// ดึง metadata ของแต่ละผลตามลำดับที่จัดไว้
+fn load_hits(store: &ReviewStore, ranked: Vec<Ranked>) -> Result<Vec<SearchHit>, (StatusCode, String)> {
+    let mut results = Vec::with_capacity(ranked.len());
+
+    for (rank, r) in ranked.into_iter().enumerate() {
+        match store.get(r.id) {
+            Ok(Some(stored)) => results.push(SearchHit {
+                id: r.id,
+                version: stored.version,
+                rank: rank + 1,
+                score: r.score,
+                distance: r.distance,
+                bm25: r.bm25,
+                review: stored.review,
+            }),
+            Ok(None) => eprintln!("Review {} is in the index but not in metadata", r.id),
+            Err(e) => {
+                eprintln!("Cannot read review {}: {}", r.id, e);
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Cannot read review {}: {}", r.id, e)));
+            }
+        }
     }
 
+    Ok(results)
+}
+
+async fn search_reviews(Json(query): Json<SearchQuery>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    println!("Received search query: {:?} ({:?})", query.query, query.mode);
+
+    let params = search_params(&query)?;
+    let filter = review_filter(query.product_id.clone(), query.min_rating, query.max_rating)?;
+    let weight = query.hybrid_weight.unwrap_or(DEFAULT_HYBRID_WEIGHT);
+    if !(0.0..=1.0).contains(&weight) {
+        return Err((StatusCode::BAD_REQUEST, "hybrid_weight must be between 0 and 1".to_string()));
+    }
+
+    let mode = query.mode;
+    let search = move || search_blocking(&query, &params, &filter, weight);
+    let results = match mode {
+        SearchMode::Lexical => worker::run_blocking(search).await?,
+        SearchMode::Semantic | SearchMode::Hybrid => worker::run_embedding(search).await?,
+    };
+
     Ok(AxumJson(SearchResult { reviews: results }))
 }
 
+/// ส่วนของ `search_reviews` ที่ block (embed คำค้น, อ่าน index และ store)
+fn search_blocking(
+    query: &SearchQuery,
+    params: &SearchParams,
+    filter: &ReviewFilter,
+    weight: f32,
+) -> Result<Vec<SearchHit>, (StatusCode, String)> {
+    let q_embedding = match query.mode {
+        SearchMode::Lexical => None,
+        SearchMode::Semantic | SearchMode::Hybrid => Some(embed_query(&query.query)?),
+    };
+
+    let mut ranked = {
+        let index = INDEX.read().unwrap();
+        let metadata = METADATA_INDEX.read().unwrap();
+        let semantic = |params: &SearchParams| {
+            semantic_matches(&index, q_embedding.as_deref().unwrap_or_default(), params, filter, &metadata.attributes)
+        };
+        match query.mode {
+            SearchMode::Semantic => semantic(params),
+            SearchMode::Lexical => lexical_matches(&metadata.lexical, &query.query, params.top_k, filter, &metadata.attributes),
+            SearchMode::Hybrid => {
+                let candidates = params.top_k * HYBRID_CANDIDATES;
+                let wide = SearchParams { top_k: candidates, ..params.clone() };
+                let lexical = lexical_matches(&metadata.lexical, &query.query, candidates, filter, &metadata.attributes);
+                fuse(semantic(&wide), lexical, weight, params.top_k)
+            }
+        }
+    };
+
+    // กันกรณี review ถูกลบระหว่างที่กำลัง insert เข้า index
+    {
+        let tombstones = TOMBSTONES.read().unwrap();
+        ranked.retain(|r| !tombstones.contains(r.id));
+    }
+
+    let store = STORE.lock().unwrap();
+    load_hits(&store, ranked)
+}
+
+async fn metrics() -> impl IntoResponse {
+    AxumJson(Metrics {
+        query_embedding_cache: QUERY_CACHE.stats(),
+        review_embedding_cache: REVIEW_CACHE.stats(),
+    })
+}
+
+/// `backend compact`: compact ไฟล์ใน data/ ขณะที่ server หยุดอยู่
+fn compact_offline() -> std::io::Result<()> {
+    compact::recover(VECTOR_PATH, METADATA_PATH)?;
+    let (header, _) = vector_file::inspect(VECTOR_PATH)?;
+    let tombstones = TombstoneLog::open(TOMBSTONE_PATH)?;
+    let (store, report) = compact::write_generation(VECTOR_PATH, METADATA_PATH, header.dim as usize, header.count, |id| {
+        tombstones.contains(id)
+    })?;
+    drop(store);
+    compact::swap(VECTOR_PATH, METADATA_PATH)?;
+    report.print();
+    Ok(())
+}
+
+/// compact ขณะ server ทำงาน: เขียน generation ใหม่โดยไม่ถือ lock ถือ lock ของ store แค่ตอนต่อท้าย
+/// record ที่เพิ่มเข้ามาระหว่างนั้นและตอนสลับไฟล์ แล้วสร้าง partition ของ spfresh ใหม่นอก lock
+/// search ทำงานต่อได้ตลอด ยกเว้นช่วงสลับไฟล์สั้น ๆ
+fn compact_online() -> std::io::Result<compact::Report> {
+    let count = STORE.lock().unwrap().len();
+    let (mut next, report) = compact::write_generation(VECTOR_PATH, METADATA_PATH, EMBEDDER.dim(), count, |id| {
+        TOMBSTONES.read().unwrap().contains(id)
+    })?;
+
+    let snapshot = {
+        let mut store = STORE.lock().unwrap();
+        for (stored, vector) in store.records_from(count)? {
+            next.append_stored(&stored, &vector)?;
+        }
+        next.reserve_ids(store.vectors().next_id())?;
+        drop(next);
+        // ถ้าสลับไม่สำเร็จก่อนสร้าง marker ไฟล์เดิมยังไม่ถูกแตะ ใช้ store เดิมต่อได้
+        // ถ้าสร้าง marker แล้วต้องสลับต่อให้ครบ ทำไม่ได้ก็ต้อง restart ให้ recover จาก marker
+        // (ไม่ panic ระหว่างถือ lock เพราะ STORE จะ poison แต่ process ยังรับ request อยู่)
+        if let Err(e) = compact::swap(VECTOR_PATH, METADATA_PATH) {
+            match compact::recover(VECTOR_PATH, METADATA_PATH) {
+                Ok(false) => return Err(e),
+                Ok(true) => eprintln!("⚠️ Compaction swap failed ({}) but was finished on retry", e),
+                Err(retry) => exit_unrecoverable(&format!("Compaction swap failed: {} (retry: {})", e, retry)),
+            }
+        }
+        match ReviewStore::open(VECTOR_PATH, METADATA_PATH, EMBEDDER.dim(), EMBEDDER.model_id()) {
+            Ok((reopened, _)) => *store = reopened,
+            Err(e) => exit_unrecoverable(&format!("Cannot reopen compacted review store: {}", e)),
+        }
+        store.len()
+    };
+
+    let mut index = Index::build(EMBEDDER.dim(), IndexConfig::default(), vector_file::read_records(VECTOR_PATH, snapshot)?);
+    {
+        let mut store = STORE.lock().unwrap();
+        let tail = store.records_from(snapshot)?;
+        let mut current = INDEX.write().unwrap();
+        for (stored, vector) in tail {
+            index.insert(stored.id, vector);
+        }
+        for id in TOMBSTONES.read().unwrap().ids() {
+            index.remove(id);
+        }
+        *current = index;
+    }
+
+    Ok(report)
+}
+
+/// store ในหน่วยความจำไม่ตรงกับไฟล์แล้ว หยุด process ให้ recover ตอน start ใหม่
+fn exit_unrecoverable(message: &str) -> ! {
+    eprintln!("❌ {}, exiting so the next start can recover", message);
+    std::process::exit(1);
+}
+
+/// compact ใน background ทุก `interval` เมื่อมี record ที่ไม่ใช้แล้ว (version เก่าหรือถูกลบ)
+fn spawn_compactor(interval: Duration) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(interval);
+            let records = STORE.lock().unwrap().len();
+            let live = INDEX.read().unwrap().len() as u64;
+            if records <= live {
+                continue;
+            }
+            match compact_online() {
+                Ok(report) => report.print(),
+                Err(e) => eprintln!("⚠️ Compaction failed: {}", e),
+            }
+        }
+    })
+}
+
 #[tokio::main]
 async fn main() {
-    
+    match std::env::args().nth(1).as_deref() {
+        None | Some("serve") => serve().await,
+        Some("check") => {
+            let report = check::check(VECTOR_PATH, METADATA_PATH, EMBEDDER.dim(), EMBEDDER.model_id()).expect("Cannot check data files");
+            report.print();
+            std::process::exit(if report.is_ok() { 0 } else { 1 });
+        }
+        Some("rebuild-index") => {
+            if let Err(e) = check::rebuild(VECTOR_PATH, METADATA_PATH, &EMBEDDER) {
+                eprintln!("Rebuild failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some("compact") => {
+            if let Err(e) = compact_offline() {
+                eprintln!("Compaction failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(other) => {
+            eprintln!("Unknown command: {}", other);
+            eprintln!("usage: backend [serve | check | rebuild-index | compact]");
+            std::process::exit(2);
+        }
+    }
+}
+
+async fn serve() {
     println!("🚀 Starting backend server...");
 
+    // เปิด store (recover ส่วนที่ไม่ commit) แล้วตรวจว่า vector กับ metadata ตรงกันก่อน
+    Lazy::force(&STORE);
+    Lazy::force(&TOMBSTONES);
+    let report = check::check(VECTOR_PATH, METADATA_PATH, EMBEDDER.dim(), EMBEDDER.model_id()).expect("Cannot check data files");
+    if !report.is_ok() {
+        report.print();
+        panic!("Refusing to start: index and metadata disagree, run `backend check` and `backend rebuild-index`");
+    }
+
+    // โหลด vector และสร้าง index ก่อนเปิดรับ request
+    // แล้วให้ background thread คอย split/merge/reassign posting ตอนมี insert ใหม่
+    spawn_rebuilder(INDEX.clone(), Duration::from_millis(500), 16);
+
+    // compact ใน background ถ้าตั้ง COMPACTION_INTERVAL_SECS
+    if let Ok(secs) = std::env::var("COMPACTION_INTERVAL_SECS") {
+        let secs: u64 = secs.parse().expect("COMPACTION_INTERVAL_SECS must be a number of seconds");
+        spawn_compactor(Duration::from_secs(secs));
+        println!("Background compaction every {}s", secs);
+    }
+    Lazy::force(&METADATA_INDEX);
+
+    // ทำงานนำเข้าที่ค้างจากครั้งก่อนต่อ
+    for job in JOBS.unfinished() {
+        settle_pending_batch(&job).expect("Cannot save import job state");
+        println!("Resuming import job {} from row {}", job.snapshot().id, job.snapshot().processed);
+        spawn_job(job);
+    }
+
     // // สร้าง CORS middleware ที่อนุญาต Origin
     //     let cors = CorsLayer::new()
     //     .allow_origin(AllowOrigin::exact("http://localhost:3000".parse().unwrap())) // อนุญาตจาก localhost:3000
@@ -190,9 +1618,16 @@ async fn main() {
         .allow_headers(Any);
 
     let app = Router::new()
-        .route("/reviews", post(insert_review))
+        .route("/reviews", get(list_reviews).post(insert_review))
         .route("/reviews/bulk", post(insert_bulk_reviews))
+        .route("/reviews/import", post(import_reviews).layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)))
+        .route("/reviews/:id", get(get_review).put(update_review).delete(delete_review))
+        .route("/reviews/:id/history", get(review_history))
+        .route("/jobs", post(create_job).layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)))
+        .route("/jobs/:id", get(get_job))
+        .route("/jobs/:id/cancel", post(cancel_job))
         .route("/search", post(search_reviews))
+        .route("/metrics", get(metrics))
         .layer(
             ServiceBuilder::new()
                 .layer(cors)
@@ -211,3 +1646,65 @@ async fn main() {
         .await
         .unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(title: &str) -> Review {
+        Review {
+            review_title: title.to_string(),
+            review_body: String::new(),
+            product_id: "P1".to_string(),
+            review_rating: 5,
+        }
+    }
+
+    #[test]
+    fn search_returns_best_match_first() {
+        let dir = std::env::temp_dir().join(format!("backend-rank-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (mut store, _) = ReviewStore::open(dir.join("reviews.index"), dir.join("reviews.jsonl"), 2, TextEmbedding::hashing(2).model_id()).unwrap();
+
+        // far ถูกเพิ่มก่อน เพื่อให้ลำดับในไฟล์ต่างจากลำดับความใกล้
+        let far = store.append(&review("far"), &[10.0, 10.0]).unwrap();
+        let tie_a = store.append(&review("tie a"), &[1.0, 0.0]).unwrap();
+        let best = store.append(&review("best"), &[0.1, 0.0]).unwrap();
+        let tie_b = store.append(&review("tie b"), &[1.0, 0.0]).unwrap();
+
+        let index = Index::build(2, IndexConfig::default(), store.read_vectors().unwrap());
+        let params = SearchParams { top_k: 4, ..Default::default() };
+        let matches = semantic_matches(&index, &[0.0, 0.0], &params, &ReviewFilter::default(), &AttributeIndex::default());
+        let hits = load_hits(&store, matches).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let ids: Vec<ReviewId> = hits.iter().map(|h| h.id).collect();
+        assert_eq!(ids, vec![best, tie_a, tie_b, far]);
+        assert_eq!(hits[0].review.review_title, "best");
+        assert_eq!(hits.iter().map(|h| h.rank).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert!(hits.windows(2).all(|w| w[0].score >= w[1].score));
+    }
+
+    #[test]
+    fn deleting_twice_succeeds_and_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("backend-delete-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let model = TextEmbedding::hashing(2).model_id();
+        let (mut store, _) = ReviewStore::open(dir.join("reviews.index"), dir.join("reviews.jsonl"), 2, model).unwrap();
+        let id = store.append(&review("gone"), &[1.0, 0.0]).unwrap();
+        let tombstones = RwLock::new(TombstoneLog::open(dir.join("reviews.tombstones")).unwrap());
+
+        let deleted = delete_stored(&store, &tombstones, id).unwrap();
+        assert_eq!(deleted.map(|s| s.review.review_title).as_deref(), Some("gone"));
+        // ลบซ้ำไม่ error (handler ตอบ 204) และไม่ต้องเอาออกจาก index อีก
+        assert!(delete_stored(&store, &tombstones, id).unwrap().is_none());
+        assert_eq!(delete_stored(&store, &tombstones, id + 1).unwrap_err().0, StatusCode::NOT_FOUND);
+
+        drop(tombstones);
+        let tombstones = RwLock::new(TombstoneLog::open(dir.join("reviews.tombstones")).unwrap());
+        assert!(tombstones.read().unwrap().contains(id));
+        assert!(delete_stored(&store, &tombstones, id).unwrap().is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
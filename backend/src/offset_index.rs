@@ -0,0 +1,262 @@
+//! sidecar index ของ metadata: review id → ตำแหน่งบรรทัดใน `reviews.jsonl`
+//!
+//! เก็บที่ `<metadata>.offsets` เป็น record ขนาดคงที่ 24 bytes ต่อ review
+//! (id, offset, ความยาวบรรทัด เป็น u64 little-endian) เรียงตามลำดับบรรทัด
+//! ต่อท้ายทุกครั้งที่ commit review ใหม่ ทำให้ search อ่าน review ได้ด้วยการ seek ตรง ๆ
+//! และตอน start ไม่ต้องอ่าน metadata ทั้งไฟล์
+//!
+//! ไฟล์นี้สร้างใหม่จาก metadata ได้เสมอ จึงไม่ fsync ถ้าข้อมูลขาดหรือไม่ตรงกับ metadata
+//! ตอนเปิดจะเติมหรือสร้างใหม่ให้เอง
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub const ENTRY_LEN: usize = 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub id: u64,
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl Entry {
+    pub fn end(&self) -> u64 {
+        self.offset + self.len
+    }
+
+    fn encode(&self) -> [u8; ENTRY_LEN] {
+        let mut buf = [0u8; ENTRY_LEN];
+        buf[0..8].copy_from_slice(&self.id.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.offset.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.len.to_le_bytes());
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        Self {
+            id: u64::from_le_bytes(buf[0..8].try_into().unwrap()),
+            offset: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+            len: u64::from_le_bytes(buf[16..24].try_into().unwrap()),
+        }
+    }
+}
+
+/// path ของ sidecar สำหรับไฟล์ metadata
+pub fn sidecar_path(metadata_path: &Path) -> PathBuf {
+    let mut name = metadata_path.as_os_str().to_owned();
+    name.push(".offsets");
+    PathBuf::from(name)
+}
+
+/// อ่าน entry ทั้งหมดโดยไม่แก้ไขไฟล์ (ใช้ตอนตรวจสอบ)
+pub fn read(path: &Path) -> io::Result<Vec<Entry>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    Ok(decode_all(&bytes))
+}
+
+fn decode_all(bytes: &[u8]) -> Vec<Entry> {
+    bytes.chunks_exact(ENTRY_LEN).map(Entry::decode).collect()
+}
+
+pub struct OffsetIndex {
+    file: File,
+    entries: Vec<Entry>,
+    /// id → ตำแหน่งใน `entries` ของทุก version เรียงจากเก่าไปใหม่
+    by_id: HashMap<u64, Vec<usize>>,
+    /// ไฟล์ไม่ตรงกับ `entries` (เขียนไม่สำเร็จ) ต้องเขียนใหม่ทั้งไฟล์
+    stale: bool,
+}
+
+impl OffsetIndex {
+    /// เปิด sidecar (สร้างใหม่ถ้ายังไม่มี) เศษ entry ที่เขียนไม่ครบท้ายไฟล์จะถูกตัดทิ้ง
+    /// ยังไม่ตรวจกับ metadata
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        if bytes.len() % ENTRY_LEN != 0 {
+            file.set_len((bytes.len() - bytes.len() % ENTRY_LEN) as u64)?;
+        }
+
+        let mut index = Self {
+            file,
+            entries: Vec::new(),
+            by_id: HashMap::new(),
+            stale: false,
+        };
+        for entry in decode_all(&bytes) {
+            index.track(entry);
+        }
+        Ok(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn last(&self) -> Option<Entry> {
+        self.entries.last().copied()
+    }
+
+    /// ตำแหน่งของ version ล่าสุดของ `id`
+    pub fn get(&self, id: u64) -> Option<Entry> {
+        self.by_id.get(&id).and_then(|v| v.last()).map(|&i| self.entries[i])
+    }
+
+    /// ตำแหน่งของทุก version ของ `id` เรียงจากเก่าไปใหม่
+    pub fn versions(&self, id: u64) -> Vec<Entry> {
+        self.by_id.get(&id).map_or_else(Vec::new, |v| v.iter().map(|&i| self.entries[i]).collect())
+    }
+
+    /// เก็บไว้แค่ `n` entry แรก
+    pub fn truncate(&mut self, n: usize) -> io::Result<()> {
+        if n >= self.entries.len() {
+            return Ok(());
+        }
+        for e in self.entries.drain(n..).rev() {
+            if let Some(v) = self.by_id.get_mut(&e.id) {
+                v.pop();
+                if v.is_empty() {
+                    self.by_id.remove(&e.id);
+                }
+            }
+        }
+        if self.stale {
+            return self.rewrite();
+        }
+        self.file.set_len((n * ENTRY_LEN) as u64)
+    }
+
+    /// แทนที่ทั้งไฟล์ด้วย `entries`
+    pub fn reset(&mut self, entries: Vec<Entry>) -> io::Result<()> {
+        self.entries.clear();
+        self.by_id.clear();
+        for entry in entries {
+            self.track(entry);
+        }
+        self.rewrite()
+    }
+
+    /// เพิ่ม entry ใน memory เสมอ แล้วต่อท้ายไฟล์ ถ้าเขียนไม่สำเร็จ (ซึ่งอาจเขียนไปได้บางส่วน)
+    /// จะเขียนใหม่ทั้งไฟล์ และถ้ายังไม่สำเร็จจะลองอีกครั้งตอน push ครั้งถัดไป
+    pub fn push(&mut self, entry: Entry) -> io::Result<()> {
+        self.track(entry);
+        if self.stale {
+            return self.rewrite();
+        }
+        self.file.write_all(&entry.encode()).or_else(|e| {
+            self.stale = true;
+            self.rewrite().map_err(|_| e)
+        })
+    }
+
+    fn rewrite(&mut self) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * ENTRY_LEN);
+        for e in &self.entries {
+            bytes.extend_from_slice(&e.encode());
+        }
+        self.stale = true;
+        self.file.set_len(0)?;
+        self.file.write_all(&bytes)?;
+        self.stale = false;
+        Ok(())
+    }
+
+    fn track(&mut self, entry: Entry) {
+        self.by_id.entry(entry.id).or_default().push(self.entries.len());
+        self.entries.push(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("backend-offsets-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        sidecar_path(&dir.join("reviews.jsonl"))
+    }
+
+    fn entry(id: u64, offset: u64) -> Entry {
+        Entry { id, offset, len: 10 }
+    }
+
+    #[test]
+    fn open_creates_a_missing_sidecar() {
+        let path = temp_path("missing");
+        let index = OffsetIndex::open(&path).unwrap();
+        assert_eq!(index.len(), 0);
+        assert!(index.last().is_none());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn entries_and_versions_survive_reopen() {
+        let path = temp_path("reopen");
+        let mut index = OffsetIndex::open(&path).unwrap();
+        for e in [entry(1, 0), entry(2, 10), entry(1, 20)] {
+            index.push(e).unwrap();
+        }
+        drop(index);
+
+        let index = OffsetIndex::open(&path).unwrap();
+        assert_eq!(index.entries(), [entry(1, 0), entry(2, 10), entry(1, 20)]);
+        assert_eq!(index.get(1), Some(entry(1, 20)));
+        assert_eq!(index.versions(1), [entry(1, 0), entry(1, 20)]);
+        assert_eq!(index.get(3), None);
+        assert!(index.versions(3).is_empty());
+        assert_eq!(read(&path).unwrap(), index.entries());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn open_drops_a_partially_written_entry() {
+        let path = temp_path("partial");
+        let mut index = OffsetIndex::open(&path).unwrap();
+        index.push(entry(1, 0)).unwrap();
+        index.push(entry(2, 10)).unwrap();
+        drop(index);
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 5).unwrap();
+
+        let mut index = OffsetIndex::open(&path).unwrap();
+        assert_eq!(index.entries(), [entry(1, 0)]);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), ENTRY_LEN as u64);
+
+        // entry ใหม่ต้องต่อจาก entry ที่ครบ ไม่ใช่ต่อจากเศษ
+        index.push(entry(3, 10)).unwrap();
+        assert_eq!(read(&path).unwrap(), [entry(1, 0), entry(3, 10)]);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn truncate_and_reset_rewrite_the_file() {
+        let path = temp_path("truncate");
+        let mut index = OffsetIndex::open(&path).unwrap();
+        for e in [entry(1, 0), entry(2, 10), entry(1, 20)] {
+            index.push(e).unwrap();
+        }
+        index.truncate(2).unwrap();
+        assert_eq!(index.get(1), Some(entry(1, 0)));
+        assert_eq!(read(&path).unwrap(), [entry(1, 0), entry(2, 10)]);
+        index.truncate(5).unwrap();
+        assert_eq!(index.len(), 2);
+
+        index.reset(vec![entry(7, 0)]).unwrap();
+        assert_eq!(index.get(1), None);
+        assert_eq!(index.get(7), Some(entry(7, 0)));
+        assert_eq!(read(&path).unwrap(), [entry(7, 0)]);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,490 @@
+//! ที่เก็บ review: ไฟล์ vector (`reviews.index`) คู่กับไฟล์ metadata (`reviews.jsonl`)
+//!
+//! ทุก review มี id (u64) ที่ server แจกเพิ่มขึ้นเรื่อย ๆ เก็บไว้ทั้งใน record ของ vector
+//! และใน field `id` ของ metadata บรรทัดที่ i ของ metadata คู่กับ vector ลำดับที่ i
+//! การแก้ review คือการต่อท้าย record ใหม่ที่ id เดิมแต่ `version` เพิ่มขึ้น record หลังสุดของ id คือ version ปัจจุบัน
+//! สองไฟล์นี้ต้อง commit พร้อมกัน
+//! โดยใช้จำนวน vector ใน header ของ `reviews.index` เป็น commit point เดียว:
+//!
+//! 1. เขียน vector ต่อท้าย (ยังไม่ commit)
+//! 2. เขียน metadata หนึ่งบรรทัดต่อท้ายแล้ว fsync
+//! 3. fsync vector แล้วเพิ่มจำนวนใน header (commit)
+//!
+//! ถ้า process ตายระหว่างทาง ตอนเปิดครั้งถัดไปจะตัด vector ที่เกินจำนวนใน header ทิ้ง
+//! และเก็บ metadata ไว้แค่จำนวนบรรทัดเท่ากับจำนวน vector ส่วนที่เกินจะถูกย้ายไปไฟล์
+//! `<metadata>.orphaned` ก่อนตัดทิ้ง เพื่อไม่ให้ข้อมูลหายเงียบ ๆ
+//!
+//! ตำแหน่งของแต่ละบรรทัดเก็บไว้ใน sidecar `<metadata>.offsets` ซึ่งต่อท้ายหลัง commit
+//! และถูกตัดหรือเติมให้ตรงกับจำนวน vector ตอนเปิด
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+use fastembed::ModelId;
+use serde::Deserialize;
+
+use crate::offset_index::{self, Entry, OffsetIndex};
+use crate::vector_file::VectorFile;
+use crate::{Review, StoredReview};
+
+pub type ReviewId = u64;
+
+/// อ่านแค่ id จากบรรทัด metadata
+#[derive(Deserialize)]
+struct IdOnly {
+    id: ReviewId,
+}
+
+/// สิ่งที่ถูกตัดทิ้งตอนเปิด store
+#[derive(Debug, Default)]
+pub struct RecoveryReport {
+    pub vector_bytes_truncated: u64,
+    pub metadata_bytes_truncated: u64,
+}
+
+impl RecoveryReport {
+    pub fn is_clean(&self) -> bool {
+        self.vector_bytes_truncated == 0 && self.metadata_bytes_truncated == 0
+    }
+}
+
+pub struct ReviewStore {
+    vectors: VectorFile,
+    metadata: File,
+    /// ความยาวของ metadata ที่ commit แล้ว
+    metadata_len: u64,
+    /// id → ตำแหน่งบรรทัดใน metadata
+    offsets: OffsetIndex,
+}
+
+impl ReviewStore {
+    /// เปิดไฟล์คู่ พร้อม recover ส่วนที่ยังไม่ commit ตามที่อธิบายไว้ด้านบน
+    /// ตำแหน่งบรรทัดอ่านจาก sidecar (ดู [`offset_index`](crate::offset_index))
+    /// อ่าน metadata เฉพาะส่วนท้ายที่ sidecar ยังไม่มี
+    pub fn open(
+        vector_path: impl AsRef<Path>,
+        metadata_path: impl AsRef<Path>,
+        dim: usize,
+        model: ModelId,
+    ) -> io::Result<(Self, RecoveryReport)> {
+        let metadata_path = metadata_path.as_ref();
+        let (vectors, vector_bytes_truncated) = VectorFile::open(vector_path, dim, model)?;
+        let count = vectors.len() as usize;
+
+        let mut metadata = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(metadata_path)?;
+        let mut offsets = OffsetIndex::open(&offset_index::sidecar_path(metadata_path))?;
+        offsets.truncate(count)?;
+        if !sidecar_matches(&metadata, &offsets)? {
+            offsets.reset(Vec::new())?;
+        }
+
+        let start = offsets.last().map_or(0, |e| e.end());
+        let mut tail = Vec::new();
+        metadata.seek(SeekFrom::Start(start))?;
+        metadata.read_to_end(&mut tail)?;
+
+        let mut offset = start;
+        for line in tail.split_inclusive(|&b| b == b'\n') {
+            if offsets.len() == count || !line.ends_with(b"\n") {
+                break;
+            }
+            let IdOnly { id } = serde_json::from_slice(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{}: line {} at offset {} has no review id ({}), run `backend rebuild-index`",
+                        metadata_path.display(),
+                        offsets.len(),
+                        offset,
+                        e
+                    ),
+                )
+            })?;
+            offsets.push(Entry { id, offset, len: line.len() as u64 })?;
+            offset += line.len() as u64;
+        }
+
+        if offsets.len() < count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{}: has {} complete lines but {} vectors are committed",
+                    metadata_path.display(),
+                    offsets.len(),
+                    count
+                ),
+            ));
+        }
+
+        let committed = offset;
+        let orphan = &tail[(committed - start) as usize..];
+        let metadata_bytes_truncated = orphan.len() as u64;
+        if metadata_bytes_truncated > 0 {
+            let mut orphaned = OpenOptions::new().create(true).append(true).open(orphaned_path(metadata_path))?;
+            orphaned.write_all(orphan)?;
+            orphaned.write_all(b"\n")?;
+            orphaned.sync_all()?;
+
+            metadata.set_len(committed)?;
+            metadata.sync_all()?;
+        }
+
+        let store = Self {
+            vectors,
+            metadata,
+            metadata_len: committed,
+            offsets,
+        };
+        let report = RecoveryReport {
+            vector_bytes_truncated,
+            metadata_bytes_truncated,
+        };
+        Ok((store, report))
+    }
+
+    pub fn dim(&self) -> usize {
+        self.vectors.dim()
+    }
+
+    /// จำนวน review ที่ commit แล้ว
+    pub fn len(&self) -> u64 {
+        self.vectors.len()
+    }
+
+    pub fn vectors(&self) -> &VectorFile {
+        &self.vectors
+    }
+
+    /// อ่าน `(id, vector)` ทั้งหมดเรียงตามลำดับในไฟล์
+    pub fn read_vectors(&mut self) -> io::Result<Vec<(ReviewId, Vec<f32>)>> {
+        self.vectors.read_all()
+    }
+
+    /// อ่าน record (metadata + vector) ตั้งแต่ลำดับที่ `start` (0-based) จนถึง record ล่าสุด
+    pub fn records_from(&mut self, start: u64) -> io::Result<Vec<(StoredReview, Vec<f32>)>> {
+        let vectors = self.vectors.read_from(start)?;
+        let entries = &self.offsets.entries()[(start as usize).min(self.offsets.len())..];
+        entries
+            .iter()
+            .zip(vectors)
+            .map(|(entry, (_, vector))| {
+                let mut line = vec![0u8; entry.len as usize];
+                self.metadata.read_exact_at(&mut line, entry.offset)?;
+                Ok((serde_json::from_slice(&line)?, vector))
+            })
+            .collect()
+    }
+
+    /// เลื่อน review id ถัดไปให้ไม่ต่ำกว่า `next_id`
+    pub fn reserve_ids(&mut self, next_id: ReviewId) -> io::Result<()> {
+        self.vectors.reserve_ids(next_id)
+    }
+
+    /// อ่าน review ที่ commit แล้วทั้งหมดเรียงตามลำดับในไฟล์
+    pub fn read_reviews(&self) -> io::Result<Vec<StoredReview>> {
+        let mut bytes = vec![0u8; self.metadata_len as usize];
+        self.metadata.read_exact_at(&mut bytes, 0)?;
+        bytes
+            .split_inclusive(|&b| b == b'\n')
+            .map(|line| serde_json::from_slice(line).map_err(io::Error::from))
+            .collect()
+    }
+
+    /// อ่าน version ล่าสุดของ review ตาม id ด้วยการ seek ไปที่บรรทัดนั้นตรง ๆ
+    pub fn get(&self, id: ReviewId) -> io::Result<Option<StoredReview>> {
+        let Some(entry) = self.offsets.get(id) else {
+            return Ok(None);
+        };
+        let mut line = vec![0u8; entry.len as usize];
+        self.metadata.read_exact_at(&mut line, entry.offset)?;
+        Ok(Some(serde_json::from_slice(&line)?))
+    }
+
+    /// ทุก version ของ review `id` เรียงจากเก่าไปใหม่ (ว่างถ้าไม่มี id นี้)
+    pub fn history(&self, id: ReviewId) -> io::Result<Vec<StoredReview>> {
+        self.offsets
+            .versions(id)
+            .into_iter()
+            .map(|entry| {
+                let mut line = vec![0u8; entry.len as usize];
+                self.metadata.read_exact_at(&mut line, entry.offset)?;
+                Ok(serde_json::from_slice(&line)?)
+            })
+            .collect()
+    }
+
+    /// เพิ่ม review หนึ่งรายการ (vector + metadata) แบบ atomic คืน id ใหม่ของ review
+    pub fn append(&mut self, review: &Review, vector: &[f32]) -> io::Result<ReviewId> {
+        let id = self.vectors.next_id();
+        self.append_stored(&StoredReview::first(id, review.clone()), vector)?;
+        Ok(id)
+    }
+
+    /// ต่อท้าย version ใหม่ของ review `id` ที่มีอยู่แล้ว (ไม่แก้บรรทัดเดิม)
+    /// คืนเลข version ใหม่ หรือ `None` ถ้าไม่มี id นี้
+    pub fn append_version(&mut self, id: ReviewId, review: &Review, vector: &[f32]) -> io::Result<Option<u32>> {
+        let Some(latest) = self.get(id)? else {
+            return Ok(None);
+        };
+        let stored = StoredReview {
+            id,
+            version: latest.version + 1,
+            review: review.clone(),
+        };
+        self.append_stored(&stored, vector)?;
+        Ok(Some(stored.version))
+    }
+
+    /// เพิ่มหลาย review ใน commit เดียว: ถ้าไม่สำเร็จจะไม่มี review ไหนถูกเขียนเลย
+    /// คืน id ใหม่ตามลำดับที่ส่งมา
+    pub fn append_all(&mut self, reviews: &[(&Review, &[f32])]) -> io::Result<Vec<ReviewId>> {
+        let first = self.vectors.next_id();
+        let records: Vec<(StoredReview, &[f32])> = reviews
+            .iter()
+            .zip(first..)
+            .map(|(&(review, vector), id)| (StoredReview::first(id, review.clone()), vector))
+            .collect();
+        self.append_records(&records)?;
+        Ok(records.iter().map(|(stored, _)| stored.id).collect())
+    }
+
+    /// ต่อท้าย record ตามที่กำหนด (ใช้ตอน rebuild เพื่อคง id และ version เดิม)
+    /// ถ้าเขียนไม่สำเร็จจะ rollback ทั้งสองไฟล์กลับเป็นสถานะก่อนเรียก
+    pub fn append_stored(&mut self, stored: &StoredReview, vector: &[f32]) -> io::Result<()> {
+        self.append_records(&[(stored.clone(), vector)])
+    }
+
+    fn append_records(&mut self, records: &[(StoredReview, &[f32])]) -> io::Result<()> {
+        let result = self.try_append(records);
+        if result.is_err() {
+            // คืน error ของการเขียน ส่วน rollback ที่ไม่สำเร็จจะถูกตัดทิ้งตอน open ครั้งถัดไป
+            if let Err(e) = self.vectors.rollback() {
+                eprintln!("⚠️ Failed to roll back vector file: {}", e);
+            }
+            if let Err(e) = self.metadata.set_len(self.metadata_len) {
+                eprintln!("⚠️ Failed to roll back metadata file: {}", e);
+            }
+        }
+        result
+    }
+
+    /// เขียน vector ทั้งหมด (ยังไม่ commit) แล้ว metadata ทั้งหมด fsync แล้วเพิ่มจำนวนใน header ครั้งเดียว
+    fn try_append(&mut self, records: &[(StoredReview, &[f32])]) -> io::Result<()> {
+        let mut lines = Vec::new();
+        let mut entries = Vec::with_capacity(records.len());
+        for (stored, vector) in records {
+            self.vectors.write_pending(stored.id, vector)?;
+            let start = lines.len();
+            serde_json::to_writer(&mut lines, stored)?;
+            lines.push(b'\n');
+            entries.push(Entry {
+                id: stored.id,
+                offset: self.metadata_len + start as u64,
+                len: (lines.len() - start) as u64,
+            });
+        }
+
+        self.metadata.seek(SeekFrom::Start(self.metadata_len))?;
+        self.metadata.write_all(&lines)?;
+        self.metadata.sync_data()?;
+
+        self.vectors.commit()?;
+        self.metadata_len += lines.len() as u64;
+        // sidecar สร้างใหม่ได้จาก metadata เขียนไม่สำเร็จก็ไม่ต้อง rollback (entry ยังอยู่ใน memory)
+        for entry in entries {
+            if let Err(e) = self.offsets.push(entry) {
+                eprintln!("⚠️ Failed to write offset index for review {}: {}", entry.id, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// entry สุดท้ายใน sidecar ชี้ไปที่บรรทัดที่มี id เดียวกันใน metadata จริงหรือไม่
+/// ถ้าไม่ตรง (เช่น metadata ถูกแทนที่) sidecar จะถูกสร้างใหม่ทั้งไฟล์
+fn sidecar_matches(metadata: &File, offsets: &OffsetIndex) -> io::Result<bool> {
+    let Some(last) = offsets.last() else {
+        return Ok(true);
+    };
+    if last.end() > metadata.metadata()?.len() {
+        return Ok(false);
+    }
+    let mut line = vec![0u8; last.len as usize];
+    metadata.read_exact_at(&mut line, last.offset)?;
+    Ok(line.ends_with(b"\n") && serde_json::from_slice::<IdOnly>(&line).is_ok_and(|r| r.id == last.id))
+}
+
+fn orphaned_path(metadata_path: &Path) -> PathBuf {
+    let mut name = metadata_path.as_os_str().to_owned();
+    name.push(".orphaned");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL: ModelId = ModelId { kind: ModelId::HASHING, fingerprint: 0 };
+
+    fn review(title: &str) -> Review {
+        Review {
+            review_title: title.to_string(),
+            review_body: String::new(),
+            product_id: "P1".to_string(),
+            review_rating: 5,
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("backend-store-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn open(dir: &Path) -> (ReviewStore, RecoveryReport) {
+        ReviewStore::open(dir.join("reviews.index"), dir.join("reviews.jsonl"), 2, MODEL).unwrap()
+    }
+
+    #[test]
+    fn open_moves_uncommitted_metadata_to_orphaned() {
+        let dir = temp_dir("orphaned");
+        let (mut store, _) = open(&dir);
+        let kept = store.append(&review("kept"), &[1.0, 0.0]).unwrap();
+
+        // process ตายหลัง fsync metadata แต่ก่อนเพิ่มจำนวนใน header
+        let lost = StoredReview::first(store.vectors.next_id(), review("lost"));
+        store.vectors.write_pending(lost.id, &[0.0, 1.0]).unwrap();
+        let mut line = serde_json::to_vec(&lost).unwrap();
+        line.push(b'\n');
+        store.metadata.seek(SeekFrom::End(0)).unwrap();
+        store.metadata.write_all(&line).unwrap();
+        let record_len = store.vectors.header().record_len();
+        drop(store);
+
+        let (store, report) = open(&dir);
+        assert_eq!(report.vector_bytes_truncated, record_len);
+        assert_eq!(report.metadata_bytes_truncated, line.len() as u64);
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.get(kept).unwrap().unwrap().review.review_title, "kept");
+        assert!(store.get(lost.id).unwrap().is_none());
+        let orphaned = std::fs::read(orphaned_path(&dir.join("reviews.jsonl"))).unwrap();
+        assert!(orphaned.starts_with(&line));
+        drop(store);
+
+        let (_, report) = open(&dir);
+        assert!(report.is_clean());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_append_leaves_nothing_behind() {
+        let dir = temp_dir("rollback");
+        let (mut store, _) = open(&dir);
+        let first = store.append(&review("first"), &[1.0, 0.0]).unwrap();
+        let metadata_len = store.metadata_len;
+
+        assert!(store.append(&review("wrong size"), &[0.0, 1.0, 2.0]).is_err());
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.metadata.metadata().unwrap().len(), metadata_len);
+
+        let second = store.append(&review("second"), &[0.0, 1.0]).unwrap();
+        assert_eq!(second, first + 1);
+        drop(store);
+
+        let (mut store, report) = open(&dir);
+        assert!(report.is_clean());
+        assert_eq!(store.get(second).unwrap().unwrap().review.review_title, "second");
+        assert_eq!(store.read_vectors().unwrap(), vec![(first, vec![1.0, 0.0]), (second, vec![0.0, 1.0])]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn versions_are_kept_in_order_across_reopen() {
+        let dir = temp_dir("versions");
+        let (mut store, _) = open(&dir);
+        let id = store.append(&review("v1"), &[1.0, 0.0]).unwrap();
+        let other = store.append(&review("other"), &[0.0, 1.0]).unwrap();
+        assert_eq!(store.append_version(id, &review("v2"), &[0.5, 0.5]).unwrap(), Some(2));
+        assert_eq!(store.append_version(id, &review("v3"), &[0.2, 0.8]).unwrap(), Some(3));
+        assert_eq!(store.append_version(other + 1, &review("missing"), &[0.0, 0.0]).unwrap(), None);
+        drop(store);
+
+        let (store, _) = open(&dir);
+        let history: Vec<(u32, String)> =
+            store.history(id).unwrap().into_iter().map(|s| (s.version, s.review.review_title)).collect();
+        assert_eq!(history, [(1, "v1".to_string()), (2, "v2".to_string()), (3, "v3".to_string())]);
+        assert_eq!(store.get(id).unwrap().unwrap().review.review_title, "v3");
+        assert_eq!(store.history(other).unwrap().len(), 1);
+        assert!(store.history(other + 1).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_append_all_rolls_back_the_whole_batch() {
+        let dir = temp_dir("rollback-batch");
+        let (mut store, _) = open(&dir);
+        let first = store.append(&review("first"), &[1.0, 0.0]).unwrap();
+        let metadata_len = store.metadata_len;
+
+        // record แรกเขียนลงไฟล์แล้วก่อนที่ record ที่สองจะผิด
+        let (ok, wrong) = (review("ok"), review("wrong size"));
+        let batch: Vec<(&Review, &[f32])> = vec![(&ok, &[0.0, 1.0]), (&wrong, &[0.0, 1.0, 2.0])];
+        assert!(store.append_all(&batch).is_err());
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.vectors.next_id(), first + 1);
+        assert_eq!(store.metadata.metadata().unwrap().len(), metadata_len);
+        drop(store);
+
+        let (mut store, report) = open(&dir);
+        assert!(report.is_clean());
+        assert_eq!(store.read_vectors().unwrap(), vec![(first, vec![1.0, 0.0])]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn open_rebuilds_missing_or_damaged_sidecar() {
+        let dir = temp_dir("sidecar");
+        let sidecar = offset_index::sidecar_path(&dir.join("reviews.jsonl"));
+        let (mut store, _) = open(&dir);
+        let first = store.append(&review("first"), &[1.0, 0.0]).unwrap();
+        let second = store.append_version(first, &review("first v2"), &[1.0, 1.0]).unwrap();
+        let third = store.append(&review("third"), &[0.0, 1.0]).unwrap();
+        assert_eq!(second, Some(2));
+        drop(store);
+
+        let check = |dir: &Path| {
+            let (store, _) = open(dir);
+            let latest = store.get(first).unwrap().unwrap();
+            assert_eq!((latest.version, latest.review.review_title.as_str()), (2, "first v2"));
+            assert_eq!(store.history(first).unwrap().len(), 2);
+            assert_eq!(store.get(third).unwrap().unwrap().review.review_title, "third");
+            assert_eq!(std::fs::metadata(&sidecar).unwrap().len(), 3 * offset_index::ENTRY_LEN as u64);
+        };
+
+        std::fs::remove_file(&sidecar).unwrap();
+        check(&dir);
+
+        // entry สุดท้ายเขียนได้ไม่ครบ
+        let len = std::fs::metadata(&sidecar).unwrap().len();
+        OpenOptions::new().write(true).open(&sidecar).unwrap().set_len(len - 5).unwrap();
+        check(&dir);
+
+        // sidecar ชี้ไปผิดบรรทัด (เช่น metadata ถูกแทนที่)
+        let mut bytes = std::fs::read(&sidecar).unwrap();
+        let last = bytes.len() - offset_index::ENTRY_LEN;
+        bytes[last..last + 8].copy_from_slice(&99u64.to_le_bytes());
+        std::fs::write(&sidecar, bytes).unwrap();
+        check(&dir);
+
+        // sidecar มี entry เกินจำนวนที่ commit ใน vector file
+        let mut bytes = std::fs::read(&sidecar).unwrap();
+        bytes.extend_from_within(..offset_index::ENTRY_LEN);
+        std::fs::write(&sidecar, bytes).unwrap();
+        check(&dir);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
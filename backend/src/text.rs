@@ -0,0 +1,229 @@
+//! เตรียมข้อความก่อน embed และก่อนทำ lexical index
+//!
+//! ข้อความที่ embed ผ่านแค่ [`preprocess`]: NFKC, ตัด zero-width, รวม `ํ` + `า` เป็น `ำ`
+//! ตัวพิมพ์ เครื่องหมาย และการเว้นวรรคคงเดิมเพราะ model ใช้ข้อมูลเหล่านี้ได้
+//!
+//! lexical index ใช้ [`tokenize`] ซึ่ง [`normalize`] หนักกว่า (ตัวพิมพ์เล็ก, ตัดสระ/วรรณยุกต์ที่พิมพ์ซ้ำติดกัน)
+//! แล้วตัดคำ ภาษาไทยไม่เว้นวรรคระหว่างคำ จึงตัดด้วย dictionary แบบ maximal matching
+//! (เลือกการตัดที่มีอักษรที่ไม่อยู่ใน dictionary น้อยที่สุด แล้วจำนวนคำน้อยที่สุด)
+//! ส่วนข้อความภาษาอื่นตัดที่ช่องว่างและเครื่องหมาย
+//!
+//! dictionary (`thai_words.txt`) มีแค่คำที่พบบ่อยในรีวิวสินค้าไม่กี่ร้อยคำ คำที่ไม่มีจะถูกรวมเป็นก้อนเดียว
+//! กับอักษรที่ไม่รู้จักข้างเคียง จึงค้นเจอเฉพาะเมื่อพิมพ์ก้อนนั้นทั้งก้อน
+
+use std::collections::HashSet;
+
+use once_cell::sync::Lazy;
+use unicode_normalization_alignments::UnicodeNormalization;
+
+const NIKHAHIT: char = '\u{0E4D}';
+const SARA_AA: char = '\u{0E32}';
+const SARA_AM: char = '\u{0E33}';
+
+struct Dictionary {
+    words: HashSet<String>,
+    /// ความยาวคำที่ยาวที่สุด (จำนวน char)
+    max_len: usize,
+}
+
+static DICTIONARY: Lazy<Dictionary> = Lazy::new(|| {
+    let words: HashSet<String> = include_str!("thai_words.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(normalize)
+        .collect();
+    let max_len = words.iter().map(|w| w.chars().count()).max().unwrap_or(1);
+    Dictionary { words, max_len }
+});
+
+fn is_thai(c: char) -> bool {
+    ('\u{0E00}'..='\u{0E7F}').contains(&c)
+}
+
+/// ไปยาลน้อย ไม้ยมก ฟองมัน อังคั่นคู่ โคมุต: ใช้เป็นตัวคั่นคำ
+fn is_thai_punctuation(c: char) -> bool {
+    matches!(c, '\u{0E2F}' | '\u{0E46}' | '\u{0E4F}' | '\u{0E5A}' | '\u{0E5B}')
+}
+
+/// สระบน/ล่างและวรรณยุกต์ที่ต้องเกาะกับพยัญชนะ
+fn is_thai_mark(c: char) -> bool {
+    matches!(c, '\u{0E31}' | '\u{0E34}'..='\u{0E3A}' | '\u{0E47}'..='\u{0E4E}')
+}
+
+/// NFKC แล้วตัด zero-width
+fn nfkc(text: &str) -> impl Iterator<Item = char> + '_ {
+    text.nfkc()
+        .map(|(c, _)| c)
+        .filter(|c| !matches!(c, '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{FEFF}'))
+}
+
+/// ต่อ `c` ท้าย `out` โดยรวม `ํ` + `า` เป็น `ำ`
+fn push_folded(out: &mut String, c: char) {
+    if c == SARA_AA && out.ends_with(NIKHAHIT) {
+        out.pop();
+        out.push(SARA_AM);
+    } else {
+        out.push(c);
+    }
+}
+
+/// ข้อความที่ใช้ embed: แก้เฉพาะรูปที่พิมพ์ต่างกันแต่เป็นอักษรเดียวกัน (ดูด้านบน)
+pub fn preprocess(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    nfkc(text).for_each(|c| push_folded(&mut out, c));
+    out
+}
+
+/// ทำให้ข้อความที่พิมพ์ต่างกันแต่ความหมายเดียวกันเป็นรูปเดียวกัน (สำหรับ lexical index)
+pub fn normalize(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in nfkc(text).flat_map(char::to_lowercase) {
+        if is_thai_mark(c) && out.ends_with(c) {
+            continue;
+        }
+        push_folded(&mut out, c);
+    }
+    out
+}
+
+/// การตัดที่ดีที่สุดที่จบที่ตำแหน่งหนึ่ง
+#[derive(Clone, Copy)]
+struct Step {
+    /// (จำนวนอักษรที่ไม่รู้จัก, จำนวนคำ) ยิ่งน้อยยิ่งดี
+    cost: (usize, usize),
+    /// ตำแหน่งเริ่มของคำสุดท้าย
+    start: usize,
+    /// คำสุดท้ายอยู่ใน dictionary หรือไม่
+    known: bool,
+}
+
+/// ตัดข้อความไทยที่ไม่มีช่องว่างเป็นคำ อักษรที่ไม่อยู่ใน dictionary ที่ติดกันรวมเป็นหนึ่งคำ
+fn segment_thai(chars: &[char], out: &mut Vec<String>) {
+    let dict = &*DICTIONARY;
+    let n = chars.len();
+    // best[i] = การตัด chars[..i] ที่ดีที่สุด
+    let mut best: Vec<Option<Step>> = vec![None; n + 1];
+    best[0] = Some(Step { cost: (0, 0), start: 0, known: true });
+
+    for i in 0..n {
+        let Some(Step { cost: (unknown, words), .. }) = best[i] else {
+            continue;
+        };
+        let mut relax = |end: usize, cost: (usize, usize), known: bool| {
+            if best[end].is_none_or(|s| cost < s.cost) {
+                best[end] = Some(Step { cost, start: i, known });
+            }
+        };
+        relax(i + 1, (unknown + 1, words + 1), false);
+        let mut word = String::new();
+        for (len, &c) in chars[i..].iter().take(dict.max_len).enumerate() {
+            word.push(c);
+            if dict.words.contains(&word) {
+                relax(i + len + 1, (unknown, words + 1), true);
+            }
+        }
+    }
+
+    let mut pieces = Vec::new();
+    let mut end = n;
+    while end > 0 {
+        let Step { start, known, .. } = best[end].expect("every position is reachable");
+        pieces.push((start, end, known));
+        end = start;
+    }
+    pieces.reverse();
+
+    let mut pending_unknown: Option<usize> = None;
+    for (start, end, known) in pieces {
+        if known {
+            if let Some(s) = pending_unknown.take() {
+                out.push(chars[s..start].iter().collect());
+            }
+            out.push(chars[start..end].iter().collect());
+        } else {
+            pending_unknown.get_or_insert(start);
+        }
+    }
+    if let Some(s) = pending_unknown {
+        out.push(chars[s..].iter().collect());
+    }
+}
+
+/// normalize แล้วแยกเป็นคำ: ข้อความไทยตัดด้วย dictionary ที่เหลือตัดที่ช่องว่างและเครื่องหมาย
+pub fn tokenize(text: &str) -> Vec<String> {
+    let normalized = normalize(text);
+    let mut tokens = Vec::new();
+    let mut run: Vec<char> = Vec::new();
+    let mut run_is_thai = false;
+
+    for c in normalized.chars() {
+        let thai = is_thai(c);
+        if is_thai_punctuation(c) || !(thai || c.is_alphanumeric()) {
+            flush(&mut run, run_is_thai, &mut tokens);
+            continue;
+        }
+        if !run.is_empty() && thai != run_is_thai {
+            flush(&mut run, run_is_thai, &mut tokens);
+        }
+        run_is_thai = thai;
+        run.push(c);
+    }
+    flush(&mut run, run_is_thai, &mut tokens);
+    tokens
+}
+
+fn flush(run: &mut Vec<char>, thai: bool, tokens: &mut Vec<String>) {
+    if run.is_empty() {
+        return;
+    }
+    if thai {
+        segment_thai(run, tokens);
+    } else {
+        tokens.push(run.iter().collect());
+    }
+    run.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preprocess_keeps_case_and_punctuation() {
+        assert_eq!(preprocess("Great Phone!! ดีมาก"), "Great Phone!! ดีมาก");
+        // NFKC, zero-width, นิคหิต + สระอา
+        assert_eq!(preprocess("ＡＢＣ\u{200B}ท\u{0E4D}\u{0E32}ได้"), "ABCทำได้");
+        // สระที่พิมพ์ซ้ำคงไว้ให้ model เห็น
+        assert_eq!(preprocess("ดีีี"), "ดีีี");
+    }
+
+    #[test]
+    fn normalize_folds_case_and_repeated_marks() {
+        assert_eq!(normalize("Ｇreat\u{FEFF}"), "great");
+        assert_eq!(normalize("ท\u{0E4D}\u{0E32}"), "ทำ");
+        assert_eq!(normalize("ดีีี"), "ดี");
+        assert_eq!(normalize(&normalize("ดี\u{200D}ีมาก")), normalize("ดี\u{200D}ีมาก"));
+    }
+
+    #[test]
+    fn tokenize_segments_thai_with_the_dictionary() {
+        // เลือกคำที่ยาวที่สุดที่มีใน dictionary ("ดีมาก") เพราะได้จำนวนคำน้อยกว่า
+        assert_eq!(tokenize("สินค้าดีมาก"), ["สินค้า", "ดีมาก"]);
+        assert_eq!(tokenize("ส่งเร็ว แพ็คดี"), ["ส่ง", "เร็ว", "แพ็ค", "ดี"]);
+    }
+
+    #[test]
+    fn tokenize_splits_mixed_scripts_and_punctuation() {
+        assert_eq!(tokenize("iPhone15ดีมาก, Battery-life"), ["iphone15", "ดีมาก", "battery", "life"]);
+        // ไม้ยมกเป็นตัวคั่นคำ
+        assert_eq!(tokenize("ดีๆ"), ["ดี"]);
+        assert!(tokenize("  !!  ").is_empty());
+    }
+
+    #[test]
+    fn tokenize_groups_unknown_thai_letters() {
+        // "ฮฮฮ" ไม่อยู่ใน dictionary จึงเป็นก้อนเดียวคั่นระหว่างคำที่รู้จัก
+        assert_eq!(tokenize("ดีฮฮฮมาก"), ["ดี", "ฮฮฮ", "มาก"]);
+    }
+}
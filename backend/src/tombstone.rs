@@ -0,0 +1,138 @@
+//! log ของ review ที่ถูกลบ (`data/reviews.tombstones`)
+//!
+//! ไฟล์ vector และ metadata ยังเป็น append-only การลบจึงเป็นการต่อท้าย
+//! `{"id":…,"deleted_at":…}` หนึ่งบรรทัดในไฟล์นี้แทน review ที่ถูกลบจะไม่ถูกใส่ใน index ตอน start
+//! และถูกเอาออกจาก index ทันทีที่ลบ แต่ข้อมูลเดิมยังอยู่ในไฟล์
+
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::store::ReviewId;
+
+#[derive(Serialize, Deserialize)]
+struct Tombstone {
+    id: ReviewId,
+    /// unix time (วินาที) ที่ลบ
+    deleted_at: u64,
+}
+
+pub struct TombstoneLog {
+    file: File,
+    deleted: HashSet<ReviewId>,
+}
+
+impl TombstoneLog {
+    /// เปิด log (สร้างใหม่ถ้ายังไม่มี) บรรทัดสุดท้ายที่เขียนไม่ครบจะถูกตัดทิ้ง
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let complete = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        if complete < bytes.len() {
+            file.set_len(complete as u64)?;
+            file.sync_all()?;
+        }
+
+        let mut deleted = HashSet::new();
+        for (line_no, line) in bytes[..complete].split_inclusive(|&b| b == b'\n').enumerate() {
+            let tombstone: Tombstone = serde_json::from_slice(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: line {} is not a tombstone: {}", path.display(), line_no, e),
+                )
+            })?;
+            deleted.insert(tombstone.id);
+        }
+        Ok(Self { file, deleted })
+    }
+
+    pub fn len(&self) -> usize {
+        self.deleted.len()
+    }
+
+    pub fn contains(&self, id: ReviewId) -> bool {
+        self.deleted.contains(&id)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = ReviewId> + '_ {
+        self.deleted.iter().copied()
+    }
+
+    /// บันทึกว่า `id` ถูกลบแล้ว (fsync ก่อนคืน) คืน `false` ถ้าถูกลบไปก่อนแล้ว
+    pub fn delete(&mut self, id: ReviewId) -> io::Result<bool> {
+        if self.deleted.contains(&id) {
+            return Ok(false);
+        }
+        let deleted_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let mut line = serde_json::to_vec(&Tombstone { id, deleted_at })?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.deleted.insert(id);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("backend-tombstone-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("reviews.tombstones")
+    }
+
+    #[test]
+    fn deletes_are_replayed_after_reopen() {
+        let path = temp_path("replay");
+        let mut log = TombstoneLog::open(&path).unwrap();
+        assert!(log.delete(3).unwrap());
+        assert!(log.delete(7).unwrap());
+        // ลบซ้ำไม่เขียนบรรทัดใหม่
+        let len = std::fs::metadata(&path).unwrap().len();
+        assert!(!log.delete(3).unwrap());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+        drop(log);
+
+        let log = TombstoneLog::open(&path).unwrap();
+        assert_eq!(log.len(), 2);
+        assert!(log.contains(3) && log.contains(7) && !log.contains(5));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn open_drops_a_partially_written_last_line() {
+        let path = temp_path("torn");
+        let mut log = TombstoneLog::open(&path).unwrap();
+        log.delete(1).unwrap();
+        drop(log);
+        let complete = std::fs::metadata(&path).unwrap().len();
+        // process ตายระหว่างเขียนบรรทัดของ id 2
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"id\":2,\"dele").unwrap();
+
+        let mut log = TombstoneLog::open(&path).unwrap();
+        assert!(log.contains(1) && !log.contains(2));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), complete);
+        assert!(log.delete(2).unwrap());
+        drop(log);
+        assert!(TombstoneLog::open(&path).unwrap().contains(2));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn open_rejects_a_corrupt_complete_line() {
+        let path = temp_path("corrupt");
+        std::fs::write(&path, b"{\"id\":1,\"deleted_at\":0}\nnot json\n").unwrap();
+        assert_eq!(TombstoneLog::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,493 @@
+//! ไฟล์ vector แบบมี header (`data/reviews.index`)
+//!
+//! layout (little-endian):
+//!
+//! ```text
+//! offset  size  field
+//!      0     4  magic "SPFV"
+//!      4     2  format version (ปัจจุบัน 3)
+//!      6     1  element type (1 = f32)
+//!      7     1  ชนิดของ embedder (1 = hashing, 2 = ONNX)
+//!      8     4  dimension
+//!     12     4  (สงวนไว้ เป็น 0)
+//!     16     8  จำนวน vector
+//!     24     8  review id ถัดไปที่จะแจก
+//!     32     8  fingerprint ของ model (ดู `fastembed::ModelId`)
+//!     40     …  record ต่อกันไป: review id (u64) ตามด้วย vector dimension × 4 bytes
+//! ```
+//!
+//! จำนวน vector ใน header คือ commit point: ข้อมูลที่เขียนเกินจำนวนนี้ถือว่ายังไม่ commit
+//! และจะถูกตัดทิ้งตอนเปิดไฟล์ครั้งถัดไป
+//!
+//! ถ้าชนิดหรือ fingerprint ของ embedder ไม่ตรงกับที่ server ใช้จะไม่ยอมเปิด เพราะ vector จาก model อื่น
+//! ที่ขนาดเท่ากันเทียบกันไม่ได้
+//!
+//! version 1 ไม่มี review id ใน record และ version 2 มี fingerprint แค่ 32 bit ที่ไม่ได้มาจากเนื้อหา model
+//! ต้องสร้างไฟล์ใหม่ด้วย `backend rebuild-index`
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use fastembed::ModelId;
+
+pub const MAGIC: &[u8; 4] = b"SPFV";
+pub const FORMAT_VERSION: u16 = 3;
+pub const HEADER_LEN: u64 = 40;
+
+const COUNT_OFFSET: u64 = 16;
+const ID_LEN: u64 = 8;
+
+/// ชนิดของค่าแต่ละตัวใน vector
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ElementType {
+    F32 = 1,
+}
+
+impl ElementType {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            1 => Some(ElementType::F32),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> u64 {
+        match self {
+            ElementType::F32 => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    pub element_type: ElementType,
+    pub dim: u32,
+    pub model: ModelId,
+    pub count: u64,
+    pub next_id: u64,
+}
+
+impl Header {
+    fn new(dim: u32, model: ModelId) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            element_type: ElementType::F32,
+            dim,
+            model,
+            count: 0,
+            next_id: 1,
+        }
+    }
+
+    /// ขนาดของ record หนึ่งอัน (id + vector)
+    pub fn record_len(&self) -> u64 {
+        ID_LEN + self.dim as u64 * self.element_type.size()
+    }
+
+    fn encode(&self) -> [u8; HEADER_LEN as usize] {
+        let mut buf = [0u8; HEADER_LEN as usize];
+        buf[0..4].copy_from_slice(MAGIC);
+        buf[4..6].copy_from_slice(&self.version.to_le_bytes());
+        buf[6] = self.element_type as u8;
+        buf[7] = self.model.kind;
+        buf[8..12].copy_from_slice(&self.dim.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.count.to_le_bytes());
+        buf[24..32].copy_from_slice(&self.next_id.to_le_bytes());
+        buf[32..40].copy_from_slice(&self.model.fingerprint.to_le_bytes());
+        buf
+    }
+
+    fn decode(buf: &[u8; HEADER_LEN as usize]) -> io::Result<Self> {
+        if &buf[0..4] != MAGIC {
+            return Err(invalid("missing SPFV magic (not a versioned vector file)".to_string()));
+        }
+        let version = u16::from_le_bytes([buf[4], buf[5]]);
+        if version == 1 {
+            return Err(invalid("format version 1 has no review ids, run `backend rebuild-index`".to_string()));
+        }
+        if version == 2 {
+            return Err(invalid(
+                "format version 2 cannot tell ONNX models apart reliably, run `backend rebuild-index`".to_string(),
+            ));
+        }
+        if version != FORMAT_VERSION {
+            return Err(invalid(format!("unsupported format version {} (expected {})", version, FORMAT_VERSION)));
+        }
+        let element_type = ElementType::from_u8(buf[6])
+            .ok_or_else(|| invalid(format!("unknown element type {}", buf[6])))?;
+        let dim = u32::from_le_bytes(buf[8..12].try_into().unwrap());
+        let model = ModelId {
+            kind: buf[7],
+            fingerprint: u64::from_le_bytes(buf[32..40].try_into().unwrap()),
+        };
+        let count = u64::from_le_bytes(buf[16..24].try_into().unwrap());
+        let next_id = u64::from_le_bytes(buf[24..32].try_into().unwrap());
+        Ok(Self { version, element_type, dim, model, count, next_id })
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// อ่าน header และขนาดไฟล์โดยไม่แก้ไขไฟล์ (ใช้ตอนตรวจสอบ)
+pub fn inspect(path: impl AsRef<Path>) -> io::Result<(Header, u64)> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len < HEADER_LEN {
+        return Err(invalid(format!("file is {} bytes, shorter than the header", len)));
+    }
+    let mut buf = [0u8; HEADER_LEN as usize];
+    file.read_exact(&mut buf)?;
+    Ok((Header::decode(&buf)?, len))
+}
+
+/// อ่าน review id ของทุก record ที่ commit แล้วโดยไม่แก้ไขไฟล์ (ใช้ตอนตรวจสอบ)
+pub fn read_ids(path: impl AsRef<Path>) -> io::Result<Vec<u64>> {
+    let (header, len) = inspect(&path)?;
+    let available = (len - HEADER_LEN) / header.record_len();
+    let mut reader = io::BufReader::new(File::open(path)?);
+    reader.seek(SeekFrom::Start(HEADER_LEN))?;
+
+    let mut record = vec![0u8; header.record_len() as usize];
+    let mut ids = Vec::with_capacity(header.count.min(available) as usize);
+    for _ in 0..header.count.min(available) {
+        reader.read_exact(&mut record)?;
+        ids.push(u64::from_le_bytes(record[..ID_LEN as usize].try_into().unwrap()));
+    }
+    Ok(ids)
+}
+
+/// อ่าน `(id, vector)` ของ `count` record แรกโดยไม่แก้ไขไฟล์
+/// อ่านได้ขณะที่ไฟล์ถูกเปิดเขียนอยู่ เพราะ record ที่ commit แล้วไม่ถูกแก้อีก
+pub fn read_records(path: impl AsRef<Path>, count: u64) -> io::Result<Vec<(u64, Vec<f32>)>> {
+    let (header, _) = inspect(&path)?;
+    if count > header.count {
+        return Err(invalid(format!("asked for {} records but only {} are committed", count, header.count)));
+    }
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(HEADER_LEN))?;
+    let mut bytes = Vec::with_capacity((count * header.record_len()) as usize);
+    file.take(count * header.record_len()).read_to_end(&mut bytes)?;
+    Ok(decode_records(&bytes, header.record_len()))
+}
+
+fn decode_records(bytes: &[u8], record_len: u64) -> Vec<(u64, Vec<f32>)> {
+    bytes
+        .chunks_exact(record_len as usize)
+        .map(|record| {
+            let (id, values) = record.split_at(ID_LEN as usize);
+            let id = u64::from_le_bytes(id.try_into().unwrap());
+            let vector = values
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            (id, vector)
+        })
+        .collect()
+}
+
+/// ไฟล์ vector ที่เปิดอยู่ append ได้อย่างเดียว
+pub struct VectorFile {
+    file: File,
+    header: Header,
+    /// จำนวน vector ที่เขียนแล้วแต่ยังไม่ commit
+    pending: u64,
+    /// id ถัดไปหลังรวม record ที่ยังไม่ commit
+    pending_next_id: u64,
+}
+
+impl VectorFile {
+    /// เปิดไฟล์ที่ `path` ถ้าไฟล์ว่างหรือไม่มีจะเขียน header ใหม่สำหรับ `dim` และ `model`
+    /// ถ้า header ไม่ตรง (magic, version, dimension, embedder) หรือไฟล์สั้นกว่าจำนวน vector ที่ commit จะ error
+    /// ข้อมูลที่ยังไม่ commit ท้ายไฟล์จะถูกตัดทิ้ง คืนจำนวน byte ที่ตัดไปด้วย
+    pub fn open(path: impl AsRef<Path>, dim: usize, model: ModelId) -> io::Result<(Self, u64)> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let len = file.metadata()?.len();
+
+        if len == 0 {
+            let header = Header::new(dim as u32, model);
+            file.write_all(&header.encode())?;
+            file.sync_all()?;
+            return Ok((
+                Self {
+                    file,
+                    header,
+                    pending: 0,
+                    pending_next_id: header.next_id,
+                },
+                0,
+            ));
+        }
+
+        if len < HEADER_LEN {
+            return Err(invalid(format!("{}: file is {} bytes, shorter than the header", path.display(), len)));
+        }
+
+        let mut buf = [0u8; HEADER_LEN as usize];
+        file.read_exact(&mut buf)?;
+        let header = Header::decode(&buf).map_err(|e| invalid(format!("{}: {}", path.display(), e)))?;
+
+        if header.dim as usize != dim {
+            return Err(invalid(format!(
+                "{}: file has {}-dim vectors but the embedding model produces {} dims",
+                path.display(),
+                header.dim,
+                dim
+            )));
+        }
+
+        if header.model != model {
+            return Err(invalid(format!(
+                "{}: vectors were made by the {} but the server is using the {} (set FASTEMBED_MODEL_DIR to that model or run `backend rebuild-index`)",
+                path.display(),
+                header.model,
+                model
+            )));
+        }
+
+        let committed = HEADER_LEN + header.count * header.record_len();
+        if len < committed {
+            return Err(invalid(format!(
+                "{}: header says {} vectors ({} bytes) but file is only {} bytes",
+                path.display(),
+                header.count,
+                committed,
+                len
+            )));
+        }
+
+        let truncated = len - committed;
+        if truncated > 0 {
+            file.set_len(committed)?;
+            file.sync_all()?;
+        }
+        Ok((
+            Self {
+                file,
+                header,
+                pending: 0,
+                pending_next_id: header.next_id,
+            },
+            truncated,
+        ))
+    }
+
+    pub fn header(&self) -> Header {
+        self.header
+    }
+
+    pub fn dim(&self) -> usize {
+        self.header.dim as usize
+    }
+
+    /// จำนวน vector ในไฟล์
+    pub fn len(&self) -> u64 {
+        self.header.count
+    }
+
+    /// review id ถัดไปที่ยังไม่ถูกใช้ (รวม record ที่ยังไม่ commit)
+    pub fn next_id(&self) -> u64 {
+        self.pending_next_id
+    }
+
+    /// อ่าน `(id, vector)` ทั้งหมดเรียงตามลำดับในไฟล์
+    pub fn read_all(&mut self) -> io::Result<Vec<(u64, Vec<f32>)>> {
+        self.read_from(0)
+    }
+
+    /// อ่าน `(id, vector)` ตั้งแต่ record ลำดับที่ `start` (0-based) จนถึง record ที่ commit ล่าสุด
+    pub fn read_from(&mut self, start: u64) -> io::Result<Vec<(u64, Vec<f32>)>> {
+        let record_len = self.header.record_len();
+        let count = self.header.count.saturating_sub(start);
+        let mut bytes = Vec::with_capacity((count * record_len) as usize);
+        self.file.seek(SeekFrom::Start(HEADER_LEN + start * record_len))?;
+        (&mut self.file).take(count * record_len).read_to_end(&mut bytes)?;
+        Ok(decode_records(&bytes, record_len))
+    }
+
+    /// เขียน record `(id, vector)` ต่อท้ายแต่ยังไม่เพิ่มจำนวนใน header
+    /// คืนลำดับ (0-based) ที่ record จะได้เมื่อ [`commit`](Self::commit)
+    pub fn write_pending(&mut self, id: u64, vector: &[f32]) -> io::Result<u64> {
+        if vector.len() != self.dim() {
+            return Err(invalid(format!("vector has {} dims, file expects {}", vector.len(), self.dim())));
+        }
+
+        let mut record = Vec::with_capacity(self.header.record_len() as usize);
+        record.extend_from_slice(&id.to_le_bytes());
+        for v in vector {
+            record.extend_from_slice(&v.to_le_bytes());
+        }
+
+        let position = self.header.count + self.pending;
+        self.file.seek(SeekFrom::Start(HEADER_LEN + position * self.header.record_len()))?;
+        self.file.write_all(&record)?;
+        self.pending += 1;
+        self.pending_next_id = self.pending_next_id.max(id + 1);
+        Ok(position)
+    }
+
+    /// sync ข้อมูลที่เขียนไว้ แล้วเพิ่มจำนวน vector และ id ถัดไปใน header (commit point)
+    pub fn commit(&mut self) -> io::Result<()> {
+        if self.pending == 0 {
+            return Ok(());
+        }
+        self.file.sync_data()?;
+
+        let count = self.header.count + self.pending;
+        self.write_count(count, self.pending_next_id)?;
+
+        self.header.count = count;
+        self.header.next_id = self.pending_next_id;
+        self.pending = 0;
+        Ok(())
+    }
+
+    /// เลื่อน review id ถัดไปให้ไม่ต่ำกว่า `next_id`
+    /// (ใช้ตอน compact เพื่อไม่ให้แจก id ของ review ที่ถูกตัดทิ้งไปแล้วซ้ำ)
+    pub fn reserve_ids(&mut self, next_id: u64) -> io::Result<()> {
+        if next_id <= self.header.next_id {
+            return Ok(());
+        }
+        self.file.seek(SeekFrom::Start(COUNT_OFFSET + 8))?;
+        self.file.write_all(&next_id.to_le_bytes())?;
+        self.file.sync_data()?;
+        self.header.next_id = next_id;
+        self.pending_next_id = self.pending_next_id.max(next_id);
+        Ok(())
+    }
+
+    /// ทิ้งข้อมูลที่เขียนไว้แต่ยังไม่ commit
+    /// เขียนจำนวนที่ commit ล่าสุดกลับลง header ก่อนตัดไฟล์ เพราะ [`commit`](Self::commit) ที่ล้มเหลวตอน sync
+    /// อาจเขียนจำนวนใหม่ลงไฟล์ไปแล้ว
+    pub fn rollback(&mut self) -> io::Result<()> {
+        self.pending = 0;
+        self.pending_next_id = self.header.next_id;
+        self.write_count(self.header.count, self.header.next_id)?;
+        self.file.set_len(HEADER_LEN + self.header.count * self.header.record_len())?;
+        self.file.sync_data()
+    }
+
+    /// เขียนจำนวน vector และ id ถัดไปลง header แล้ว sync
+    fn write_count(&mut self, count: u64, next_id: u64) -> io::Result<()> {
+        let mut buf = [0u8; 16];
+        buf[0..8].copy_from_slice(&count.to_le_bytes());
+        buf[8..16].copy_from_slice(&next_id.to_le_bytes());
+        self.file.seek(SeekFrom::Start(COUNT_OFFSET))?;
+        self.file.write_all(&buf)?;
+        self.file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL: ModelId = ModelId { kind: ModelId::HASHING, fingerprint: 0 };
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("backend-vector-file-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("reviews.index")
+    }
+
+    #[test]
+    fn open_truncates_uncommitted_records() {
+        let path = temp_path("truncate");
+        let (mut file, _) = VectorFile::open(&path, 2, MODEL).unwrap();
+        file.write_pending(1, &[1.0, 2.0]).unwrap();
+        file.commit().unwrap();
+        // process ตายหลังเขียน record แต่ก่อน commit
+        file.write_pending(2, &[3.0, 4.0]).unwrap();
+        file.write_pending(3, &[5.0, 6.0]).unwrap();
+        let record_len = file.header().record_len();
+        drop(file);
+
+        let (mut file, truncated) = VectorFile::open(&path, 2, MODEL).unwrap();
+        assert_eq!(truncated, 2 * record_len);
+        assert_eq!(file.len(), 1);
+        assert_eq!(file.next_id(), 2);
+        assert_eq!(file.read_all().unwrap(), vec![(1, vec![1.0, 2.0])]);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), HEADER_LEN + record_len);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn rollback_discards_pending_records() {
+        let path = temp_path("rollback");
+        let (mut file, _) = VectorFile::open(&path, 2, MODEL).unwrap();
+        file.write_pending(1, &[1.0, 2.0]).unwrap();
+        file.commit().unwrap();
+        file.write_pending(2, &[3.0, 4.0]).unwrap();
+        assert_eq!(file.next_id(), 3);
+        file.rollback().unwrap();
+
+        assert_eq!(file.len(), 1);
+        assert_eq!(file.next_id(), 2);
+        // record ถัดไปต้องเขียนทับตำแหน่งที่ rollback ไป
+        assert_eq!(file.write_pending(2, &[7.0, 8.0]).unwrap(), 1);
+        file.commit().unwrap();
+        drop(file);
+
+        let (mut file, truncated) = VectorFile::open(&path, 2, MODEL).unwrap();
+        assert_eq!(truncated, 0);
+        assert_eq!(file.read_all().unwrap(), vec![(1, vec![1.0, 2.0]), (2, vec![7.0, 8.0])]);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn rollback_restores_the_committed_header() {
+        let path = temp_path("rollback-header");
+        let (mut file, _) = VectorFile::open(&path, 2, MODEL).unwrap();
+        file.write_pending(1, &[1.0, 2.0]).unwrap();
+        file.commit().unwrap();
+        // commit เขียนจำนวนใหม่ลง header แล้วแต่ sync ไม่สำเร็จ
+        file.write_pending(2, &[3.0, 4.0]).unwrap();
+        file.write_count(2, 3).unwrap();
+        file.rollback().unwrap();
+        drop(file);
+
+        let (header, _) = inspect(&path).unwrap();
+        assert_eq!((header.count, header.next_id), (1, 2));
+        let (mut file, truncated) = VectorFile::open(&path, 2, MODEL).unwrap();
+        assert_eq!(truncated, 0);
+        assert_eq!(file.read_all().unwrap(), vec![(1, vec![1.0, 2.0])]);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn open_checks_the_recorded_embedder() {
+        let path = temp_path("model");
+        drop(VectorFile::open(&path, 2, MODEL).unwrap());
+        let onnx = ModelId { kind: ModelId::ONNX, fingerprint: 42 };
+        assert!(VectorFile::open(&path, 2, onnx).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        // fingerprint เก็บครบ 64 bit: model ที่ต่างกันเฉพาะ 32 bit บนก็ไม่ยอมเปิด
+        drop(VectorFile::open(&path, 2, onnx).unwrap());
+        assert_eq!(inspect(&path).unwrap().0.model, onnx);
+        let other = ModelId { kind: ModelId::ONNX, fingerprint: 42 | 1 << 32 };
+        let err = VectorFile::open(&path, 2, other).err().unwrap();
+        assert!(err.to_string().contains("rebuild-index"));
+        assert!(VectorFile::open(&path, 2, onnx).is_ok());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn open_refuses_version_2_files() {
+        let path = temp_path("version-2");
+        drop(VectorFile::open(&path, 2, MODEL).unwrap());
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(4)).unwrap();
+        file.write_all(&2u16.to_le_bytes()).unwrap();
+        drop(file);
+        let err = VectorFile::open(&path, 2, MODEL).err().unwrap();
+        assert!(err.to_string().contains("rebuild-index"));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
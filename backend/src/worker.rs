@@ -0,0 +1,137 @@
+//! ย้ายงานที่ block (embed, อ่าน/เขียนไฟล์, lock ของ store และ index) ออกจาก async handler
+//!
+//! งานทั้งหมดรันใน blocking thread ของ tokio ผ่าน `spawn_blocking` เพื่อไม่ให้ worker ของ runtime ค้าง
+//! งานที่ต้อง embed ต้องได้ที่ว่างในคิว (สูงสุด `MAX_QUEUED`) ก่อน ถ้าคิวเต็มจะตอบ `429` ทันที
+//! และรอ worker (เท่าจำนวน CPU) ได้ไม่เกิน `QUEUE_TIMEOUT` ถ้านานกว่านั้นตอบ `503`
+//! งานนำเข้าแบบ background ไม่ผ่านคิวแต่ใช้ worker ร่วมกับ request ทีละชุด (ดู [`with_worker`])
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use once_cell::sync::Lazy;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// งาน embed ที่รับไว้ได้พร้อมกัน (ทั้งที่กำลังทำและที่รอ worker)
+const MAX_QUEUED: usize = 64;
+/// เวลาที่งานรอ worker ได้นานที่สุด
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(30);
+
+static QUEUE: Semaphore = Semaphore::const_new(MAX_QUEUED);
+
+/// จำนวนงาน embed ที่ทำพร้อมกันได้ เท่าจำนวน CPU
+static WORKERS: Lazy<Semaphore> =
+    Lazy::new(|| Semaphore::new(std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)));
+
+/// รัน `work` ใน blocking thread
+pub async fn run_blocking<T, F>(work: F) -> Result<T, (StatusCode, String)>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, (StatusCode, String)> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work).await.map_err(|e| {
+        eprintln!("Blocking task failed: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, format!("Blocking task failed: {}", e))
+    })?
+}
+
+/// รันงานที่ต้อง embed ผ่านคิวที่จำกัดขนาด (ดูด้านบน) ที่ว่างในคิวและ worker ถูกถือไว้จนงานเสร็จ
+/// แม้ client จะตัดการเชื่อมต่อไปก่อน
+pub async fn run_embedding<T, F>(work: F) -> Result<T, (StatusCode, String)>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, (StatusCode, String)> + Send + 'static,
+{
+    let slot = reserve()?;
+    let worker = acquire_worker(&WORKERS, QUEUE_TIMEOUT).await?;
+    run_blocking(move || {
+        let _permits = (slot, worker);
+        work()
+    })
+    .await
+}
+
+/// ที่ว่างในคิวที่จองไว้ทั้ง request สำหรับงานที่ embed เป็นหลายชุด (เช่นนำเข้าไฟล์)
+/// เพื่อไม่ให้ request ที่รับไว้แล้วได้ `429` กลางทาง
+pub struct QueueSlot {
+    _permit: SemaphorePermit<'static>,
+}
+
+/// จองที่ว่างในคิว ตอบ `429` ถ้าคิวเต็ม
+pub fn reserve() -> Result<QueueSlot, (StatusCode, String)> {
+    reserve_in(&QUEUE, MAX_QUEUED)
+}
+
+fn reserve_in(queue: &'static Semaphore, max_queued: usize) -> Result<QueueSlot, (StatusCode, String)> {
+    queue.try_acquire().map(|permit| QueueSlot { _permit: permit }).map_err(|_| {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("Embedding queue is full ({} requests), retry later", max_queued),
+        )
+    })
+}
+
+/// รอ worker ได้ไม่เกิน `timeout` ตอบ `503` ถ้าไม่มี worker ว่าง
+async fn acquire_worker(workers: &Semaphore, timeout: Duration) -> Result<SemaphorePermit<'_>, (StatusCode, String)> {
+    match tokio::time::timeout(timeout, workers.acquire()).await {
+        Ok(permit) => Ok(permit.expect("embedding worker semaphore is never closed")),
+        Err(_) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("No embedding worker became free within {}s, retry later", timeout.as_secs()),
+        )),
+    }
+}
+
+impl QueueSlot {
+    /// รันงานหนึ่งชุดเมื่อได้ worker รอ worker โดยไม่จำกัดเวลาเพราะ request ถูกรับเข้าคิวแล้ว
+    pub async fn run<T, F>(&self, work: F) -> Result<T, (StatusCode, String)>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, (StatusCode, String)> + Send + 'static,
+    {
+        let worker = WORKERS.acquire().await.expect("embedding worker semaphore is never closed");
+        run_blocking(move || {
+            let _worker = worker;
+            work()
+        })
+        .await
+    }
+}
+
+/// รอ worker แล้วรัน `work` ใน thread ปัจจุบัน สำหรับงาน background ที่รันใน blocking thread อยู่แล้ว
+/// รอโดยไม่จำกัดเวลา ใช้ทีละชุดเพื่อให้ request ได้ worker สลับกับงาน background
+pub fn with_worker<T>(work: impl FnOnce() -> T) -> T {
+    let _worker = tokio::runtime::Handle::current()
+        .block_on(WORKERS.acquire())
+        .expect("embedding worker semaphore is never closed");
+    work()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_queue_is_rejected_with_429() {
+        static QUEUE: Semaphore = Semaphore::const_new(2);
+        let first = reserve_in(&QUEUE, 2).unwrap();
+        let _second = reserve_in(&QUEUE, 2).unwrap();
+        let (status, message) = reserve_in(&QUEUE, 2).err().unwrap();
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert!(message.contains("(2 requests)"), "{}", message);
+
+        // ที่ว่างคืนเมื่อ request ก่อนหน้าเสร็จ
+        drop(first);
+        assert!(reserve_in(&QUEUE, 2).is_ok());
+    }
+
+    #[tokio::test]
+    async fn waiting_too_long_for_a_worker_is_503() {
+        let workers = Semaphore::new(1);
+        let busy = acquire_worker(&workers, Duration::from_millis(10)).await.unwrap();
+        let (status, _) = acquire_worker(&workers, Duration::from_millis(10)).await.err().unwrap();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        drop(busy);
+        assert!(acquire_worker(&workers, Duration::from_millis(10)).await.is_ok());
+    }
+}
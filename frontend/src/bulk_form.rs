@@ -1,78 +1,156 @@
 // src/bulk_form.rs
 use leptos::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use wasm_bindgen_futures::spawn_local;
-use web_sys::RequestInit;
+use web_sys::{FormData, HtmlInputElement, RequestInit};
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{Request, Response, console};
 use crate::config::BACKEND_URL;
 
-#[derive(Serialize, Clone)]
-struct Review {
+/// ชื่อ column ในไฟล์ของแต่ละ field (ส่งเป็น field `mapping` ของ form)
+#[derive(Serialize)]
+struct ColumnMapping {
     review_title: String,
     review_body: String,
     product_id: String,
-    review_rating: u8,
+    review_rating: String,
+}
+
+#[derive(Deserialize, Clone)]
+struct RowError {
+    row: u64,
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct ImportResult {
+    rows: u64,
+    ids: Vec<u64>,
+    failed: u64,
+    errors: Vec<RowError>,
 }
 
 #[component]
 pub fn BulkReviewForm() -> impl IntoView {
     let status = RwSignal::new(String::new());
-    let reviews = RwSignal::new(vec![
-        Review {
-            review_title: "Title A".into(),
-            review_body: "Body A".into(),
-            product_id: "P001".into(),
-            review_rating: 4,
-        },
-        Review {
-            review_title: "Title B".into(),
-            review_body: "Body B".into(),
-            product_id: "P002".into(),
-            review_rating: 5,
-        },
-    ]);
+    let errors = RwSignal::new(Vec::<RowError>::new());
+    let file_input = NodeRef::<leptos::html::Input>::new();
+    let title_column = RwSignal::new("review_title".to_string());
+    let body_column = RwSignal::new("review_body".to_string());
+    let product_column = RwSignal::new("product_id".to_string());
+    let rating_column = RwSignal::new("review_rating".to_string());
+
+    fn get_input_value(ev: &leptos::ev::Event) -> String {
+        ev.target()
+            .and_then(|t| t.dyn_into::<HtmlInputElement>().ok())
+            .map(|input| input.value())
+            .unwrap_or_default()
+    }
 
     let on_submit = move |ev: leptos::ev::SubmitEvent| {
         ev.prevent_default();
-        let data = serde_json::json!({ "reviews": reviews.get() });
-        let status = status.clone();
+        let Some(file) = file_input.get().and_then(|input| input.files()).and_then(|files| files.get(0)) else {
+            status.set("❌ กรุณาเลือกไฟล์ CSV หรือ JSONL".to_string());
+            return;
+        };
+        let mapping = ColumnMapping {
+            review_title: title_column.get(),
+            review_body: body_column.get(),
+            product_id: product_column.get(),
+            review_rating: rating_column.get(),
+        };
+        status.set(format!("⏳ กำลังนำเข้า {} ...", file.name()));
+        errors.set(Vec::new());
 
         spawn_local(async move {
-            let body_str = serde_json::to_string(&data).unwrap();
+            let mapping_str = serde_json::to_string(&mapping).unwrap();
 
-            // Log JSON data to console
-            console::log_1(&JsValue::from_str(&format!("📦 Sending bulk reviews: {}", body_str)));
+            // Log upload to console
+            console::log_1(&JsValue::from_str(&format!("📦 Uploading {} with mapping {}", file.name(), mapping_str)));
 
-            let opts = RequestInit::new();
+            // mapping ต้องมาก่อน file เพราะ backend อ่านไฟล์ทีละ chunk
+            let form = FormData::new().unwrap();
+            form.append_with_str("mapping", &mapping_str).unwrap();
+            form.append_with_blob_and_filename("file", &file, &file.name()).unwrap();
 
+            let opts = RequestInit::new();
             opts.set_method("POST");
-            opts.set_body(&JsValue::from_str(&body_str));
+            opts.set_body(&form);
 
-            let url = format!("{}/reviews/bulk", BACKEND_URL);
+            let url = format!("{}/reviews/import", BACKEND_URL);
             let request = Request::new_with_str_and_init(&url, &opts).unwrap();
-            request.headers().set("Content-Type", "application/json").unwrap();
 
             let window = web_sys::window().unwrap();
             let resp = JsFuture::from(window.fetch_with_request(&request)).await.unwrap();
             let resp: Response = resp.dyn_into().unwrap();
 
             if resp.ok() {
-                status.set("✅ ส่งรีวิวชุดใหญ่เรียบร้อยแล้ว".to_string());
-                window.alert_with_message("✅ ส่งรีวิวชุดใหญ่เรียบร้อยแล้ว").ok();
+                let json = JsFuture::from(resp.json().unwrap()).await.unwrap();
+                let json_string = js_sys::JSON::stringify(&json).unwrap().as_string().unwrap();
+                match serde_json::from_str::<ImportResult>(&json_string) {
+                    Ok(r) => {
+                        status.set(format!(
+                            "✅ นำเข้าแล้ว {} จาก {} แถว ({} แถวผิดพลาด)",
+                            r.ids.len(),
+                            r.rows,
+                            r.failed
+                        ));
+                        errors.set(r.errors);
+                    }
+                    Err(_) => status.set("✅ นำเข้าไฟล์เรียบร้อยแล้ว".to_string()),
+                }
             } else {
-                status.set("❌ ส่งรีวิวชุดใหญ่ไม่สำเร็จ".to_string());
-                window.alert_with_message("❌ ส่งรีวิวชุดใหญ่ไม่สำเร็จ").ok();
+                let message = JsFuture::from(resp.text().unwrap()).await.ok().and_then(|t| t.as_string()).unwrap_or_default();
+                status.set(format!("❌ นำเข้าไฟล์ไม่สำเร็จ: {}", message));
+                window.alert_with_message("❌ นำเข้าไฟล์ไม่สำเร็จ").ok();
             }
         });
     };
 
     view! {
         <form on:submit=on_submit>
-            <h2>"ส่งหลายรีวิว (Bulk)"</h2>
-            <button type="submit">"ส่งทั้งหมด"</button>
+            <h2>"นำเข้ารีวิวจากไฟล์ (CSV / JSONL)"</h2>
+
+            <input type="file" accept=".csv,.jsonl,.ndjson" node_ref=file_input />
+
+            <p>"ชื่อ column ในไฟล์:"</p>
+            <label for="title-column">"หัวข้อ:"</label>
+            <input
+                id="title-column"
+                type="text"
+                on:input=move |ev| title_column.set(get_input_value(&ev))
+                prop:value=move || title_column.get()
+            />
+            <label for="body-column">"เนื้อหา:"</label>
+            <input
+                id="body-column"
+                type="text"
+                on:input=move |ev| body_column.set(get_input_value(&ev))
+                prop:value=move || body_column.get()
+            />
+            <label for="product-column">"Product ID:"</label>
+            <input
+                id="product-column"
+                type="text"
+                on:input=move |ev| product_column.set(get_input_value(&ev))
+                prop:value=move || product_column.get()
+            />
+            <label for="rating-column">"คะแนน:"</label>
+            <input
+                id="rating-column"
+                type="text"
+                on:input=move |ev| rating_column.set(get_input_value(&ev))
+                prop:value=move || rating_column.get()
+            />
+
+            <button type="submit">"อัปโหลด"</button>
             <p>{status}</p>
+            <ul>
+                {move || errors.get().into_iter().map(|e| view! {
+                    <li>{format!("แถว {}: {}", e.row, e.error)}</li>
+                }).collect_view()}
+            </ul>
         </form>
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,6 @@
 // src/view_form.rs
 use leptos::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::JsCast;
 use wasm_bindgen::JsValue;
 use wasm_bindgen_futures::{spawn_local, JsFuture};
@@ -16,6 +16,11 @@ struct Review {
     review_rating: u8,
 }
 
+#[derive(Deserialize)]
+struct InsertResult {
+    id: u64,
+}
+
 #[component]
 pub fn ReviewForm() -> impl IntoView {
     let review_title = RwSignal::new(String::new());
@@ -68,10 +73,16 @@ pub fn ReviewForm() -> impl IntoView {
             let resp: Response = resp.dyn_into().unwrap();
 
             if resp.ok() {
-                status.set("✅ ส่งรีวิวเรียบร้อยแล้ว".to_string());
+                let json = JsFuture::from(resp.json().unwrap()).await.unwrap();
+                let json_string = js_sys::JSON::stringify(&json).unwrap().as_string().unwrap();
+                let message = match serde_json::from_str::<InsertResult>(&json_string) {
+                    Ok(r) => format!("✅ ส่งรีวิวเรียบร้อยแล้ว (id {})", r.id),
+                    Err(_) => "✅ ส่งรีวิวเรียบร้อยแล้ว".to_string(),
+                };
+                status.set(message.clone());
 
                 // Show alert popup on success
-                window.alert_with_message("✅ ส่งรีวิวเรียบร้อยแล้ว").ok();
+                window.alert_with_message(&message).ok();
             } else {
                 status.set("❌ เกิดข้อผิดพลาด".to_string());
 
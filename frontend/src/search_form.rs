@@ -11,10 +11,26 @@ use crate::config::BACKEND_URL;
 #[derive(Serialize)]
 struct SearchQuery {
     query: String,
+    mode: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    product_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_rating: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_rating: Option<u8>,
+}
+
+/// ช่อง filter ที่เว้นว่างไว้ถือว่าไม่กรอง
+fn optional_text(value: String) -> Option<String> {
+    let value = value.trim().to_string();
+    if value.is_empty() { None } else { Some(value) }
 }
 
 #[derive(Deserialize, Clone, Serialize)]
-struct Review {
+struct SearchHit {
+    id: u64,
+    rank: usize,
+    score: f32,
     review_title: String,
     review_body: String,
     product_id: String,
@@ -23,13 +39,17 @@ struct Review {
 
 #[derive(Deserialize)]
 struct SearchResult {
-    reviews: Vec<Review>,
+    reviews: Vec<SearchHit>,
 }
 
 #[component]
 pub fn SearchReview() -> impl IntoView {
     let query = RwSignal::new(String::new());
-    let results = RwSignal::new(Vec::<Review>::new());
+    let mode = RwSignal::new("semantic".to_string());
+    let product_id = RwSignal::new(String::new());
+    let min_rating = RwSignal::new(String::new());
+    let max_rating = RwSignal::new(String::new());
+    let results = RwSignal::new(Vec::<SearchHit>::new());
     let status = RwSignal::new(String::new());
     let response_text = RwSignal::new(String::new());
 
@@ -39,6 +59,23 @@ pub fn SearchReview() -> impl IntoView {
         query_clone_input.set(input.value());
     };
 
+    let on_product_input = move |ev: Event| {
+        let input: web_sys::HtmlInputElement = ev.target().unwrap().unchecked_into();
+        product_id.set(input.value());
+    };
+    let on_mode = move |ev: Event| {
+        let select: web_sys::HtmlSelectElement = ev.target().unwrap().unchecked_into();
+        mode.set(select.value());
+    };
+    let on_min_rating = move |ev: Event| {
+        let select: web_sys::HtmlSelectElement = ev.target().unwrap().unchecked_into();
+        min_rating.set(select.value());
+    };
+    let on_max_rating = move |ev: Event| {
+        let select: web_sys::HtmlSelectElement = ev.target().unwrap().unchecked_into();
+        max_rating.set(select.value());
+    };
+
     let query_clone = query.clone();
     let results_clone = results.clone();
     let status_clone = status.clone();
@@ -46,6 +83,13 @@ pub fn SearchReview() -> impl IntoView {
 
     let on_search = move |_| {
         let q = query_clone.get();
+        let search_query = SearchQuery {
+            query: q.clone(),
+            mode: mode.get(),
+            product_id: optional_text(product_id.get()),
+            min_rating: min_rating.get().parse().ok(),
+            max_rating: max_rating.get().parse().ok(),
+        };
 
         if q.is_empty() {
             results_clone.set(Vec::new());
@@ -57,7 +101,7 @@ pub fn SearchReview() -> impl IntoView {
         status_clone.set("⏳ กำลังค้นหา...".to_string());
 
         spawn_local(async move {
-            let body = match serde_json::to_string(&SearchQuery { query: q.clone() }) {
+            let body = match serde_json::to_string(&search_query) {
                 Ok(b) => b,
                 Err(e) => {
                     console::log_1(&format!("❌ JSON serialize error: {:?}", e).into());
@@ -141,13 +185,38 @@ pub fn SearchReview() -> impl IntoView {
             />
             <button type="button" on:click=on_search>"ค้นหา"</button>
 
+            <div style="margin-top:8px;">
+                <select on:change=on_mode>
+                    <option value="semantic">"ความหมาย (semantic)"</option>
+                    <option value="lexical">"คำตรงตัว (lexical)"</option>
+                    <option value="hybrid">"ผสม (hybrid)"</option>
+                </select>
+                " "
+                <input
+                    type="text"
+                    placeholder="Product ID (ทั้งหมด)"
+                    on:input=on_product_input
+                    value=move || product_id.get()
+                />
+                " คะแนน "
+                <select on:change=on_min_rating>
+                    <option value="">"ต่ำสุด"</option>
+                    {(1..=5).map(|r| view! { <option value=r.to_string()>{format!("{}⭐", r)}</option> }).collect_view()}
+                </select>
+                " - "
+                <select on:change=on_max_rating>
+                    <option value="">"สูงสุด"</option>
+                    {(1..=5).map(|r| view! { <option value=r.to_string()>{format!("{}⭐", r)}</option> }).collect_view()}
+                </select>
+            </div>
+
             <p>{status.get()}</p>
             <p>"จำนวนผลลัพธ์: " {move || results.get().len()}</p>
 
             <ul style="list-style:none; padding:0;">
                 <For
                     each=move || results.get()
-                    key=|r| format!("{}-{}", r.product_id, r.review_title)
+                    key=|r| r.id
                     children=move |r| {
                         view! {
                             <li
@@ -159,9 +228,11 @@ pub fn SearchReview() -> impl IntoView {
                                     box-shadow: 1px 1px 3px rgba(0,0,0,0.1);
                                 "
                             >
+                                {format!("#{} ", r.rank)}
                                 <b>{r.review_title.clone()}</b>
                                 {" ("}{r.product_id.clone()}{") - "}
                                 {format!("{}⭐", r.review_rating)}
+                                {format!(" · คะแนนความใกล้เคียง {:.3}", r.score)}
                                 <p style="margin-top:8px;">{r.review_body.clone()}</p>
                             </li>
                         }